@@ -0,0 +1,126 @@
+use crate::{interval::Interval, ray::Ray, vec3::Point3};
+
+// Axis-aligned bounding box stored as one interval per axis
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Aabb {
+    pub(crate) x: Interval,
+    pub(crate) y: Interval,
+    pub(crate) z: Interval,
+}
+
+impl Aabb {
+    pub(crate) fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        let mut bbox = Self { x, y, z };
+        bbox.pad_to_minimums();
+        bbox
+    }
+
+    pub(crate) fn empty() -> Self {
+        Self {
+            x: Interval::empty(),
+            y: Interval::empty(),
+            z: Interval::empty(),
+        }
+    }
+
+    // Treat the two points a and b as extrema for the bounding box, in any order
+    pub(crate) fn from_points(a: Point3, b: Point3) -> Self {
+        Self::new(
+            Interval::new(a.x().min(b.x()), a.x().max(b.x())),
+            Interval::new(a.y().min(b.y()), a.y().max(b.y())),
+            Interval::new(a.z().min(b.z()), a.z().max(b.z())),
+        )
+    }
+
+    pub(crate) fn surrounding(a: &Aabb, b: &Aabb) -> Self {
+        Self {
+            x: Interval::enclosing(&a.x, &b.x),
+            y: Interval::enclosing(&a.y, &b.y),
+            z: Interval::enclosing(&a.z, &b.z),
+        }
+    }
+
+    pub(crate) fn axis_interval(&self, n: usize) -> &Interval {
+        match n {
+            0 => &self.x,
+            1 => &self.y,
+            _ => &self.z,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.x.min > self.x.max || self.y.min > self.y.max || self.z.min > self.z.max
+    }
+
+    pub(crate) fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub(crate) fn centroid(&self) -> Point3 {
+        Point3::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
+    pub(crate) fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    pub(crate) fn hit(&self, r: &Ray, ray_t: &Interval) -> bool {
+        self.clip(r, ray_t).is_some()
+    }
+
+    // The part of ray_t for which the ray is inside the box, if any
+    pub(crate) fn clip(&self, r: &Ray, ray_t: &Interval) -> Option<Interval> {
+        let ray_orig = r.origin();
+        let ray_dir = r.direction();
+        let mut ray_t = *ray_t;
+
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let adinv = 1.0 / ray_dir[axis];
+
+            let t0 = (ax.min - ray_orig[axis]) * adinv;
+            let t1 = (ax.max - ray_orig[axis]) * adinv;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            ray_t.min = ray_t.min.max(t0);
+            ray_t.max = ray_t.max.min(t1);
+            if ray_t.max <= ray_t.min {
+                return None;
+            }
+        }
+        Some(ray_t)
+    }
+
+    // Adjust the box so that no side is narrower than some delta, padding if necessary. This
+    // keeps flat primitives from producing boxes that rays can slip through.
+    fn pad_to_minimums(&mut self) {
+        let delta = 0.0001;
+        if self.x.size() < delta {
+            self.x = self.x.expand(delta);
+        }
+        if self.y.size() < delta {
+            self.y = self.y.expand(delta);
+        }
+        if self.z.size() < delta {
+            self.z = self.z.expand(delta);
+        }
+    }
+}
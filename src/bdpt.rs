@@ -0,0 +1,804 @@
+// Bidirectional path tracing, after chapter 10 of Eric Veach's thesis. Every camera sample traces
+// one subpath back from the camera and another out of the lights, then joins each prefix of one
+// to each prefix of the other. Each join is one strategy for building a path, and is weighted by
+// the power heuristic against every other split of the same path between the two subpaths.
+// Joining light subpaths straight to the camera lands on arbitrary pixels, so that light is
+// splatted; it is what finds caustics seen on diffuse surfaces.
+
+use std::f64::consts::PI;
+
+use crate::{
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    integrator::{Integrator, RenderContext},
+    interval::Interval,
+    material::ScatterRecord,
+    pdf::{CosinePdf, Pdf},
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VertexKind {
+    // On the camera's lens
+    Camera,
+    // Where a light subpath starts, on the surface of one of the lights
+    Light,
+    // Where a subpath met the scene, on a surface or inside a medium
+    Scatter,
+}
+
+#[derive(Clone)]
+struct Vertex {
+    kind: VertexKind,
+    // Camera vertices use only the position
+    rec: HitRecord,
+    // The material's attenuation, at scatter vertices that sample their next direction
+    attenuation: Color,
+    // The subpath's throughput from its start up to this vertex
+    beta: Color,
+    // Set where the material scatters along a single direction, so the vertex cannot be joined
+    delta: bool,
+    // The densities per unit area of sampling this vertex from the one before it in its subpath,
+    // and from the one after it were the path traced from the other end. Points in media take no
+    // cosine. Specular vertices and the ends of chains of them are handled as in random_walk.
+    pdf_fwd: f64,
+    pdf_rev: f64,
+}
+
+impl Vertex {
+    fn new(kind: VertexKind, rec: HitRecord, beta: Color) -> Self {
+        Self {
+            kind,
+            rec,
+            attenuation: Color::origin(),
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn camera(p: Point3) -> Self {
+        let mut rec = HitRecord::new();
+        rec.p = p;
+        Self::new(VertexKind::Camera, rec, Color::new(1.0, 1.0, 1.0))
+    }
+
+    fn p(&self) -> Point3 {
+        self.rec.p
+    }
+
+    fn on_surface(&self) -> bool {
+        self.kind != VertexKind::Camera && !self.rec.in_medium
+    }
+
+    // The cosine between the unit vector direction and the normal, or 1 off surfaces
+    fn cosine(&self, direction: &Vec3) -> f64 {
+        if self.on_surface() {
+            self.rec.normal.dot(direction).abs()
+        } else {
+            1.0
+        }
+    }
+
+    // Turns a density per unit solid angle of directions from this vertex into one per unit area
+    // around next
+    fn to_area(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.p() - self.p();
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        pdf * next.cosine(&(w / distance_squared.sqrt())) / distance_squared
+    }
+
+    // The hit record as a ray arriving from the side the unit vector toward points to would see it
+    fn facing(&self, toward: &Vec3) -> HitRecord {
+        let mut rec = self.rec.clone();
+        let outward_normal = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+        rec.set_face_normal(&Ray::new(self.p() + *toward, -*toward), outward_normal);
+        rec
+    }
+
+    // The scattering function for light arriving from toward_light and leaving towards
+    // toward_camera, both unit vectors from the vertex: the attenuation times the material's
+    // scattering_pdf for a ray from the camera's side, without the cosine that includes. Light
+    // vertices give their emission towards toward_camera.
+    fn f(&self, toward_camera: &Vec3, toward_light: &Vec3, time: f64) -> Color {
+        let Some(m) = &self.rec.mat else {
+            return Color::origin();
+        };
+        let r_in = Ray::with_time(self.p() + *toward_camera, -*toward_camera, time);
+        match self.kind {
+            VertexKind::Camera => Color::origin(),
+            VertexKind::Light => m.emitted(&r_in, &self.rec),
+            VertexKind::Scatter => {
+                let cosine = self.cosine(toward_light);
+                if cosine < 1e-8 {
+                    return Color::origin();
+                }
+                let scattered = Ray::with_time(self.p(), *toward_light, time);
+                let scattering_pdf =
+                    m.scattering_pdf(&r_in, &self.facing(toward_camera), &scattered);
+                (scattering_pdf / cosine) * self.attenuation
+            }
+        }
+    }
+
+    // The material's importance_scale for a subpath passing through this vertex from prev
+    // towards next
+    fn importance_scale(&self, prev: &Vertex, next: &Vertex, time: f64) -> f64 {
+        let Some(m) = &self.rec.mat else {
+            return 1.0;
+        };
+        let toward_prev = (prev.p() - self.p()).unit_vector();
+        let r_in = Ray::with_time(self.p() + toward_prev, -toward_prev, time);
+        let scattered = Ray::with_time(self.p(), next.p() - self.p(), time);
+        m.importance_scale(&r_in, &self.facing(&toward_prev), &scattered)
+    }
+
+    // The density per unit solid angle with which a subpath arriving along the unit vector
+    // incoming leaves along direction. Lights emit from both sides with a cosine weighting.
+    fn pdf_dir(
+        &self,
+        incoming: &Vec3,
+        direction: &Vec3,
+        context: &RenderContext,
+        time: f64,
+        sampler: &mut Sampler,
+    ) -> f64 {
+        match self.kind {
+            VertexKind::Camera => context.camera.direction_pdf(&self.p(), direction),
+            VertexKind::Light => self.emission_pdf(&direction.unit_vector()),
+            VertexKind::Scatter => {
+                let Some(m) = &self.rec.mat else {
+                    return 0.0;
+                };
+                let r_in = Ray::with_time(self.p() - *incoming, *incoming, time);
+                match m.scatter(&r_in, &self.facing(&-*incoming), sampler) {
+                    Some(ScatterRecord::Sampled { pdf, .. }) => pdf.value(direction),
+                    _ => 0.0,
+                }
+            }
+        }
+    }
+
+    // The density per unit area with which this vertex, reached from prev, samples next. Camera
+    // and light vertices have no prev.
+    fn pdf(
+        &self,
+        prev: Option<&Vertex>,
+        next: &Vertex,
+        context: &RenderContext,
+        time: f64,
+        sampler: &mut Sampler,
+    ) -> f64 {
+        let direction = (next.p() - self.p()).unit_vector();
+        let incoming = prev.map_or(Vec3::origin(), |prev| (self.p() - prev.p()).unit_vector());
+        self.to_area(
+            self.pdf_dir(&incoming, &direction, context, time, sampler),
+            next,
+        )
+    }
+
+    // For a vertex on an emitter, the density per unit area with which a light subpath starts
+    // there
+    fn light_origin_pdf(&self, lights: Option<&dyn Hittable>) -> f64 {
+        let outward_normal = if self.rec.front_face {
+            self.rec.normal
+        } else {
+            -self.rec.normal
+        };
+        lights.map_or(0.0, |lights| lights.surface_pdf(&self.p(), &outward_normal))
+    }
+
+    // The density per unit solid angle with which a light emits along the unit vector direction
+    fn emission_pdf(&self, direction: &Vec3) -> f64 {
+        self.cosine(direction) / (2.0 * PI)
+    }
+}
+
+fn is_black(c: &Color) -> bool {
+    c.x() == 0.0 && c.y() == 0.0 && c.z() == 0.0
+}
+
+// Whether nothing blocks the segment from a to b. Media block it at random, as often as they
+// would scatter a ray crossing it.
+fn visible(
+    context: &RenderContext,
+    a: Point3,
+    b: Point3,
+    time: f64,
+    sampler: &mut Sampler,
+) -> bool {
+    let d = b - a;
+    let distance = d.length();
+    let ray = Ray::with_time(a, d / distance, time);
+    context
+        .world
+        .hit(&ray, &Interval::new(0.001, distance - 0.001), sampler)
+        .is_none()
+}
+
+// Extends path, whose last vertex ray leaves, by up to max_vertices hits. beta is the throughput
+// along ray and pdf the density per unit solid angle with which it was chosen. Returns the
+// background light a camera subpath escapes to.
+//
+// How a run of specular vertices focuses light changes the density per unit area it carries a
+// sample to, which cannot be worked out from the directions alone. So the vertices at either end
+// of such a chain record, as their density from the other end, the density of the direction into
+// the chain times their own cosine with it, and specular vertices record 0. By conservation of
+// etendue the unknown factor is the same both ways but for the squared ratio of the refractive
+// indices at the two ends, which is the product of the chain's importance_scale. Each end takes
+// the square root of that, so the factor left over cancels out of mis_weight.
+#[allow(clippy::too_many_arguments)]
+fn random_walk(
+    context: &RenderContext,
+    mut ray: Ray,
+    mut beta: Color,
+    mut pdf: f64,
+    max_vertices: i64,
+    from_camera: bool,
+    path: &mut Vec<Vertex>,
+    sampler: &mut Sampler,
+) -> Color {
+    let time = ray.time();
+    // The last vertex that was not specular, and the direction it chose with density pdf
+    let mut chain_start = path.len() - 1;
+    let mut chain_direction = ray.direction().unit_vector();
+    let mut chain_scale: f64 = 1.0;
+    for _ in 0..max_vertices {
+        let Some(rec) = context.hit(&ray, sampler) else {
+            if from_camera {
+                return beta * context.background.color(&ray);
+            }
+            break;
+        };
+        let Some(m) = rec.mat.clone() else {
+            break;
+        };
+        let incoming = ray.direction().unit_vector();
+        let mut vertex = Vertex::new(VertexKind::Scatter, rec, beta);
+        let scatter = m.scatter(&ray, &vertex.rec, sampler);
+        vertex.delta = matches!(scatter, Some(ScatterRecord::Specular { .. }));
+        if !vertex.delta {
+            vertex.pdf_fwd = if chain_start == path.len() - 1 {
+                path[chain_start].to_area(pdf, &vertex)
+            } else {
+                pdf * vertex.cosine(&incoming) / chain_scale.sqrt()
+            };
+        }
+
+        match scatter {
+            None => {
+                path.push(vertex);
+                break;
+            }
+            Some(ScatterRecord::Specular {
+                attenuation,
+                ray: scattered,
+            }) => {
+                let scale = m.importance_scale(&ray, &vertex.rec, &scattered);
+                chain_scale *= scale;
+                beta = beta * attenuation;
+                if !from_camera {
+                    beta = scale * beta;
+                }
+                path.push(vertex);
+                ray = scattered;
+            }
+            Some(ScatterRecord::Sampled {
+                attenuation,
+                pdf: material_pdf,
+            }) => {
+                vertex.attenuation = attenuation;
+                let direction = material_pdf.generate(sampler).unit_vector();
+                let pdf_value = material_pdf.value(&direction);
+                if pdf_value <= 0.0 {
+                    path.push(vertex);
+                    break;
+                }
+                let f = if from_camera {
+                    vertex.f(&-incoming, &direction, time)
+                } else {
+                    vertex.f(&direction, &-incoming, time)
+                };
+                beta = (vertex.cosine(&direction) / pdf_value) * beta * f;
+
+                let reverse = vertex.pdf_dir(&-direction, &-incoming, context, time, sampler);
+                let straight = chain_start == path.len() - 1;
+                let start = &mut path[chain_start];
+                start.pdf_rev = if straight {
+                    vertex.to_area(reverse, start)
+                } else {
+                    reverse * start.cosine(&chain_direction) * chain_scale.sqrt()
+                };
+                ray = Ray::with_time(vertex.p(), direction, time);
+                path.push(vertex);
+                (chain_start, chain_direction, pdf) = (path.len() - 1, direction, pdf_value);
+                chain_scale = 1.0;
+                if is_black(&beta) {
+                    break;
+                }
+            }
+        }
+    }
+    Color::origin()
+}
+
+// A point on the lights, then a walk of up to max_vertices hits from it
+fn light_subpath(
+    context: &RenderContext,
+    max_vertices: i64,
+    time: f64,
+    sampler: &mut Sampler,
+) -> Vec<Vertex> {
+    let mut path = Vec::new();
+    let Some((rec, pdf_position)) = context
+        .lights
+        .and_then(|lights| lights.sample_surface(sampler))
+    else {
+        return path;
+    };
+    if pdf_position <= 0.0 {
+        return path;
+    }
+    let side = if sampler.random_f64() < 0.5 {
+        rec.normal
+    } else {
+        -rec.normal
+    };
+    let direction = CosinePdf::new(side).generate(sampler).unit_vector();
+
+    let inverse_pdf = 1.0 / pdf_position;
+    let mut light = Vertex::new(
+        VertexKind::Light,
+        rec,
+        Color::new(1.0, 1.0, 1.0) * inverse_pdf,
+    );
+    light.pdf_fwd = pdf_position;
+    let pdf_direction = light.emission_pdf(&direction);
+    let emitted = light.f(&direction, &Vec3::origin(), time);
+    let beta = (light.cosine(&direction) / (pdf_position * pdf_direction)) * emitted;
+    let ray = Ray::with_time(light.p(), direction, time);
+    path.push(light);
+    if pdf_direction > 0.0 && !is_black(&beta) {
+        random_walk(
+            context,
+            ray,
+            beta,
+            pdf_direction,
+            max_vertices,
+            false,
+            &mut path,
+            sampler,
+        );
+    }
+    path
+}
+
+// Finds direct lighting and caustics that unidirectional path tracing rarely does, at a few times
+// the cost per sample. Paths have at most max_depth bounces and are never ended early by
+// roulette. The lights must also be part of the world.
+pub(crate) struct Bidirectional;
+
+impl Bidirectional {
+    // The light that joining the first s vertices of light_path to the first t of camera_path,
+    // with t >= 2, carries to the camera. A vertex on the lights is sampled afresh when s = 1.
+    #[allow(clippy::too_many_arguments)]
+    fn join(
+        &self,
+        s: usize,
+        t: usize,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        context: &RenderContext,
+        time: f64,
+        sampler: &mut Sampler,
+    ) -> Color {
+        let pt = &camera_path[t - 1];
+        let toward_camera = (camera_path[t - 2].p() - pt.p()).unit_vector();
+        let (color, sampled) = match s {
+            // The camera subpath found an emitter by itself
+            0 => {
+                let Some(m) = &pt.rec.mat else {
+                    return Color::origin();
+                };
+                let r_in = Ray::with_time(pt.p() + toward_camera, -toward_camera, time);
+                (pt.beta * m.emitted(&r_in, &pt.rec), None)
+            }
+            _ => {
+                let sampled = match s {
+                    1 => {
+                        let Some((rec, pdf)) = context
+                            .lights
+                            .and_then(|lights| lights.sample_surface(sampler))
+                        else {
+                            return Color::origin();
+                        };
+                        if pdf <= 0.0 {
+                            return Color::origin();
+                        }
+                        let mut light =
+                            Vertex::new(VertexKind::Light, rec, Color::new(1.0, 1.0, 1.0) / pdf);
+                        light.pdf_fwd = pdf;
+                        Some(light)
+                    }
+                    _ => None,
+                };
+                let qs = sampled.as_ref().unwrap_or(&light_path[s - 1]);
+                if qs.delta || pt.delta {
+                    return Color::origin();
+                }
+                let toward_light = match s {
+                    1 => Vec3::origin(),
+                    _ => (light_path[s - 2].p() - qs.p()).unit_vector(),
+                };
+                let d = pt.p() - qs.p();
+                let distance_squared = d.length_squared();
+                if distance_squared == 0.0 {
+                    return Color::origin();
+                }
+                let w = d / distance_squared.sqrt();
+                let geometry = qs.cosine(&w) * pt.cosine(&w) / distance_squared;
+                let color = geometry
+                    * qs.beta
+                    * qs.f(&w, &toward_light, time)
+                    * pt.f(&toward_camera, &-w, time)
+                    * pt.beta;
+                if is_black(&color) || !visible(context, qs.p(), pt.p(), time, sampler) {
+                    return Color::origin();
+                }
+                (color, sampled)
+            }
+        };
+        if is_black(&color) {
+            return color;
+        }
+        let weight = self.mis_weight(
+            s,
+            t,
+            light_path,
+            camera_path,
+            sampled.as_ref(),
+            context,
+            time,
+            sampler,
+        );
+        weight * color
+    }
+
+    // Joins the last of the first s vertices of light_path straight to a point sampled on the
+    // lens, returning the pixel the light lands in and how much it adds there
+    fn join_camera(
+        &self,
+        s: usize,
+        light_path: &[Vertex],
+        context: &RenderContext,
+        time: f64,
+        sampler: &mut Sampler,
+    ) -> Option<((usize, usize), Color)> {
+        let qs = &light_path[s - 1];
+        if qs.delta {
+            return None;
+        }
+        let (lens, pixel) = context.camera.connect(&qs.p(), sampler)?;
+        let d = lens - qs.p();
+        let distance_squared = d.length_squared();
+        let w = d / distance_squared.sqrt();
+        let toward_light = match s {
+            1 => Vec3::origin(),
+            _ => (light_path[s - 2].p() - qs.p()).unit_vector(),
+        };
+        // The camera's density of rays, per unit lens area and solid angle, is also its
+        // importance, its sensitivity to light arriving along them. The lens point was sampled
+        // with the same density per unit area, which divides it back out.
+        let lens_pdf = context.camera.lens_pdf();
+        let importance = lens_pdf * context.camera.direction_pdf(&lens, &-d);
+        let color = (qs.cosine(&w) * importance / (lens_pdf * distance_squared))
+            * qs.beta
+            * qs.f(&w, &toward_light, time);
+        if is_black(&color) || !visible(context, qs.p(), lens, time, sampler) {
+            return None;
+        }
+        let camera = Vertex::camera(lens);
+        let weight = self.mis_weight(s, 1, light_path, &[], Some(&camera), context, time, sampler);
+        Some((pixel, weight * color))
+    }
+
+    // The power heuristic weight of joining s light vertices to t camera vertices, against
+    // moving the join anywhere else along the same path. sampled stands in for the last light
+    // vertex when s = 1 and t > 1, and for the camera vertex when t = 1.
+    #[allow(clippy::too_many_arguments)]
+    fn mis_weight(
+        &self,
+        s: usize,
+        t: usize,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        sampled: Option<&Vertex>,
+        context: &RenderContext,
+        time: f64,
+        sampler: &mut Sampler,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let qs = match s {
+            0 => None,
+            1 if t > 1 => sampled,
+            _ => Some(&light_path[s - 1]),
+        };
+        let pt = match t {
+            1 => sampled.unwrap(),
+            _ => &camera_path[t - 1],
+        };
+        let qs_minus = (s > 1).then(|| &light_path[s - 2]);
+        let pt_minus = (t > 1).then(|| &camera_path[t - 2]);
+
+        // The whole path from the light to the camera, with the densities of sampling each
+        // vertex from the light's end and from the camera's
+        let mut vertices: Vec<&Vertex> = light_path[..s.saturating_sub(1)].iter().collect();
+        vertices.extend(qs);
+        vertices.push(pt);
+        vertices.extend(camera_path[..t - 1].iter().rev());
+        let (mut from_light, mut from_camera): (Vec<f64>, Vec<f64>) = vertices
+            .iter()
+            .enumerate()
+            .map(|(k, v)| {
+                if k < s {
+                    (v.pdf_fwd, v.pdf_rev)
+                } else {
+                    (v.pdf_rev, v.pdf_fwd)
+                }
+            })
+            .unzip();
+
+        // Around the join, the subpaths' own densities give way to sampling across it
+        from_light[s] = match qs {
+            Some(qs) => qs.pdf(qs_minus, pt, context, time, sampler),
+            None => pt.light_origin_pdf(context.lights),
+        };
+        if qs.is_none() && from_light[s] == 0.0 {
+            // An emitter that is not among the lights, which only the camera subpath can find
+            return 1.0;
+        }
+        if let Some(pt_minus) = pt_minus {
+            let direction = (pt_minus.p() - pt.p()).unit_vector();
+            let pdf = match qs {
+                Some(qs) => {
+                    let incoming = (pt.p() - qs.p()).unit_vector();
+                    pt.pdf_dir(&incoming, &direction, context, time, sampler)
+                }
+                None => pt.emission_pdf(&direction),
+            };
+            carry(&vertices, &mut from_light, s, s + 1, pdf, time);
+        }
+        if let Some(qs) = qs {
+            from_camera[s - 1] = pt.pdf(pt_minus, qs, context, time, sampler);
+            if let Some(qs_minus) = qs_minus {
+                let direction = (qs_minus.p() - qs.p()).unit_vector();
+                let incoming = (qs.p() - pt.p()).unit_vector();
+                let pdf = qs.pdf_dir(&incoming, &direction, context, time, sampler);
+                carry(&vertices, &mut from_camera, s - 1, s - 2, pdf, time);
+            }
+        }
+
+        // Densities of 0 at specular vertices stand for 1, as every strategy that does not
+        // join at them samples them the same way
+        let remap = |pdf: f64| if pdf == 0.0 { 1.0 } else { pdf };
+        let n = vertices.len();
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for k in s..n - 1 {
+            ratio *= remap(from_light[k]) / remap(from_camera[k]);
+            if !vertices[k].delta && !vertices[k + 1].delta {
+                sum += ratio * ratio;
+            }
+        }
+        ratio = 1.0;
+        for k in (0..s).rev() {
+            ratio *= remap(from_camera[k]) / remap(from_light[k]);
+            if !vertices[k].delta && (k == 0 || !vertices[k - 1].delta) {
+                sum += ratio * ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+// Records in densities how likely vertices[from], choosing the direction towards vertices[next]
+// with density pdf per unit solid angle, is to reach the first vertex past any specular ones, in
+// the same terms as random_walk
+fn carry(
+    vertices: &[&Vertex],
+    densities: &mut [f64],
+    from: usize,
+    next: usize,
+    pdf: f64,
+    time: f64,
+) {
+    let step = |k: usize| if next > from { k + 1 } else { k - 1 };
+    let back = |k: usize| if next > from { k - 1 } else { k + 1 };
+    if !vertices[next].delta {
+        densities[next] = vertices[from].to_area(pdf, vertices[next]);
+        return;
+    }
+    // Both ends of a path are never specular
+    let mut k = next;
+    let mut scale: f64 = 1.0;
+    while vertices[k].delta {
+        scale *= vertices[k].importance_scale(vertices[back(k)], vertices[step(k)], time);
+        k = step(k);
+    }
+    let toward_chain = (vertices[back(k)].p() - vertices[k].p()).unit_vector();
+    densities[k] = pdf * vertices[k].cosine(&toward_chain) / scale.sqrt();
+}
+
+impl Integrator for Bidirectional {
+    fn ray_color(&self, r: Ray, context: &RenderContext, sampler: &mut Sampler) -> Color {
+        let time = r.time();
+        let mut camera_path = vec![Vertex::camera(r.origin())];
+        let pdf = context.camera.direction_pdf(&r.origin(), &r.direction());
+        let mut color = random_walk(
+            context,
+            r,
+            Color::new(1.0, 1.0, 1.0),
+            pdf,
+            context.max_depth + 1,
+            true,
+            &mut camera_path,
+            sampler,
+        );
+        let light_path = light_subpath(context, context.max_depth, time, sampler);
+
+        let mut splats = Vec::new();
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                let depth = (s + t) as i64 - 2;
+                if depth < 0 || depth > context.max_depth {
+                    continue;
+                }
+                match t {
+                    // A light point joined straight to the lens duplicates the camera seeing
+                    // the light itself, so only the latter is used
+                    1 if s > 1 => {
+                        if let Some(((x, y), splat)) =
+                            self.join_camera(s, &light_path, context, time, sampler)
+                        {
+                            splats.push((x, y, splat));
+                        }
+                    }
+                    1 => {}
+                    _ => {
+                        color += self.join(s, t, &light_path, &camera_path, context, time, sampler)
+                    }
+                }
+            }
+        }
+        if !splats.is_empty() {
+            context.splats.add(&splats);
+        }
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        bdpt::Bidirectional,
+        camera::{Background, Camera},
+        color::Color,
+        hittable::Hittable,
+        hittable_list::HittableList,
+        integrator::{
+            tests::{assert_close, brightness, camera_brightness},
+            Integrator, PathTracer,
+        },
+        material::{Dielectric, DiffuseLight, Lambertian},
+        quad::Quad,
+        sphere::Sphere,
+        vec3::{Point3, Vec3},
+    };
+
+    // A glass ball on a floor under a small light, which focuses it into a caustic, and the light
+    fn caustic() -> (HittableList, Arc<dyn Hittable>) {
+        let mut world = HittableList::empty();
+        world.add(Arc::new(Quad::new(
+            Point3::new(-2.0, 0.0, -2.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.6, 0.0),
+            0.5,
+            Arc::new(Dielectric::new(1.5)),
+        )));
+        let light: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Point3::new(-0.25, 2.0, -0.25),
+            Vec3::new(0.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.5),
+            Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 10.0))),
+        ));
+        world.add(light.clone());
+        (world, light)
+    }
+
+    #[test]
+    fn agrees_with_path_tracing() {
+        let (world, light) = caustic();
+        let lookfrom = Point3::new(0.0, 3.0, 3.0);
+        let mut path_tracer = PathTracer::new();
+        path_tracer.set_roulette_depth(None);
+        let reference = brightness(&world, light.as_ref(), &path_tracer, lookfrom, (20000, 4));
+        let b = brightness(&world, light.as_ref(), &Bidirectional, lookfrom, (2000, 4));
+        assert_close(b, reference, "bdpt");
+    }
+    #[test]
+    fn agrees_with_path_tracing_through_a_lens() {
+        // Light joined straight to the camera lands on points spread over the lens
+        let (world, light) = caustic();
+        let render = |integrator: &dyn Integrator, samples_per_pixel| {
+            let mut camera = Camera::new(
+                1.0,
+                8,
+                samples_per_pixel,
+                4,
+                Point3::new(0.0, 3.0, 3.0),
+                Point3::origin(),
+                Vec3::new(0.0, 0.0, -1.0),
+                60.0,
+                6.0,
+                3.0,
+            );
+            camera.set_background(Background::None);
+            camera_brightness(&mut camera, &world, light.as_ref(), integrator)
+        };
+        let mut path_tracer = PathTracer::new();
+        path_tracer.set_roulette_depth(None);
+        let reference = render(&path_tracer, 20000);
+        assert_close(render(&Bidirectional, 2000), reference, "bdpt with defocus");
+    }
+
+    #[test]
+    fn scales_light_crossing_into_glass() {
+        // A light sealed in a glass ball over a floor, so light reaching the floor leaves the
+        // glass once and the scaling of light traced through it does not cancel. The light sits
+        // near the center, where none of it is trapped by total internal reflection.
+        let mut world = HittableList::empty();
+        world.add(Arc::new(Quad::new(
+            Point3::new(-2.0, 0.0, -2.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.6, 0.0),
+            0.5,
+            Arc::new(Dielectric::new(1.5)),
+        )));
+        let light: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Point3::new(-0.1, 0.6, -0.1),
+            Vec3::new(0.2, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.2),
+            Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 10.0))),
+        ));
+        world.add(light.clone());
+
+        let lookfrom = Point3::new(0.0, 3.0, 3.0);
+        let mut path_tracer = PathTracer::new();
+        path_tracer.set_roulette_depth(None);
+        let reference = brightness(&world, light.as_ref(), &path_tracer, lookfrom, (20000, 4));
+        let b = brightness(&world, light.as_ref(), &Bidirectional, lookfrom, (2000, 4));
+        assert_close(b, reference, "bdpt through one glass interface");
+    }
+}
@@ -0,0 +1,390 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    camera::{Background, Camera},
+    color::Color,
+    constant_medium::ConstantMedium,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal},
+    quad::{make_box, Quad},
+    sampler::Sampler,
+    scene::Scene,
+    sphere::Sphere,
+    texture::CheckerTexture,
+    triangle::TriangleMesh,
+    vec3::{Point3, Vec3},
+};
+
+pub(crate) const BUILTIN_SCENES: &[&str] = &[
+    "random-spheres",
+    "bouncing-spheres",
+    "simple-light",
+    "cornell-box",
+    "cornell-smoke",
+    "icospheres",
+];
+
+pub(crate) fn builtin_scene(name: &str, sampler: &mut Sampler) -> Option<Scene> {
+    match name {
+        "random-spheres" => Some(random_spheres_scene(sampler, false)),
+        "bouncing-spheres" => Some(random_spheres_scene(sampler, true)),
+        "simple-light" => Some(simple_light_scene()),
+        "cornell-box" => Some(cornell_box_scene(false)),
+        "cornell-smoke" => Some(cornell_box_scene(true)),
+        "icospheres" => Some(icospheres_scene()),
+        _ => None,
+    }
+}
+
+// The final scene from Ray Tracing in One Weekend. When bouncing, the small diffuse spheres
+// move upwards during the frame as in Ray Tracing: The Next Week, blurring with motion, above
+// a checkered ground.
+fn random_spheres_scene(sampler: &mut Sampler, bouncing: bool) -> Scene {
+    let mut world = HittableList::empty();
+
+    let material_ground: Arc<dyn Material> = if bouncing {
+        Arc::new(Lambertian::from_texture(Arc::new(
+            CheckerTexture::from_colors(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)),
+        )))
+    } else {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    };
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        material_ground,
+    )));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = sampler.random_f64();
+            let center = Point3::new(
+                a as f64 + 0.9 * sampler.random_f64(),
+                0.2,
+                b as f64 + 0.9 * sampler.random_f64(),
+            );
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let mut center2 = center;
+                let sphere_material: Arc<dyn Material> = if choose_mat < 0.8 {
+                    let albedo = Color::random(sampler) * Color::random(sampler);
+                    if bouncing {
+                        center2 += Vec3::new(0.0, sampler.random_f64_range(0.0, 0.5), 0.0);
+                    }
+                    Arc::new(Lambertian::new(albedo))
+                } else if choose_mat < 0.95 {
+                    let albedo = Color::random_range(sampler, 0.5, 1.0);
+                    let fuzz = sampler.random_f64_range(0.0, 0.5);
+                    Arc::new(Metal::new(albedo, fuzz))
+                } else {
+                    Arc::new(Dielectric::new(1.5))
+                };
+                world.add(Arc::new(Sphere::moving(
+                    center,
+                    center2,
+                    0.2,
+                    sphere_material,
+                )));
+            }
+        }
+    }
+
+    let material1 = Arc::new(Dielectric::new(1.5));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        material1,
+    )));
+    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        material2,
+    )));
+    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        material3,
+    )));
+
+    let camera = Camera::new(
+        16.0 / 9.0,
+        1200,
+        500,
+        50,
+        Point3::new(13.0, 2.0, 3.0),
+        Point3::origin(),
+        Vec3::new(0.0, 1.0, 0.0),
+        20.0,
+        0.6,
+        10.0,
+    );
+    Scene {
+        camera,
+        world,
+        lights: HittableList::empty(),
+    }
+}
+
+// Two diffuse spheres lit only by a glowing sphere above them
+fn simple_light_scene() -> Scene {
+    let mut world = HittableList::empty();
+
+    let grey = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        grey.clone(),
+    )));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 2.0, 0.0), 2.0, grey)));
+
+    let mut lights = HittableList::empty();
+    let difflight = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+    let light: Arc<dyn Hittable> =
+        Arc::new(Sphere::new(Point3::new(0.0, 7.0, 0.0), 2.0, difflight));
+    world.add(light.clone());
+    lights.add(light);
+
+    let mut camera = Camera::new(
+        16.0 / 9.0,
+        400,
+        100,
+        50,
+        Point3::new(26.0, 3.0, 6.0),
+        Point3::new(0.0, 2.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        20.0,
+        0.0,
+        10.0,
+    );
+    camera.set_background(Background::None);
+    Scene {
+        camera,
+        world,
+        lights,
+    }
+}
+
+// The classic Cornell box: a white room with a red and a green wall, lit through the ceiling.
+// With smoke, the two blocks become boxes of dark and light smoke under a wider, dimmer light, as
+// in Ray Tracing: The Next Week.
+fn cornell_box_scene(smoke: bool) -> Scene {
+    let mut world = HittableList::empty();
+
+    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+
+    world.add(Arc::new(Quad::new(
+        Point3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        green,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        red,
+    )));
+    let mut lights = HittableList::empty();
+    let light: Arc<dyn Hittable> = Arc::new(if smoke {
+        Quad::new(
+            Point3::new(113.0, 554.0, 127.0),
+            Vec3::new(330.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 305.0),
+            Arc::new(DiffuseLight::new(Color::new(7.0, 7.0, 7.0))),
+        )
+    } else {
+        Quad::new(
+            Point3::new(343.0, 554.0, 332.0),
+            Vec3::new(-130.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -105.0),
+            Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0))),
+        )
+    });
+    world.add(light.clone());
+    lights.add(light);
+    world.add(Arc::new(Quad::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        white.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(555.0, 555.0, 555.0),
+        Vec3::new(-555.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -555.0),
+        white.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(0.0, 0.0, 555.0),
+        Vec3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        white.clone(),
+    )));
+
+    let short_block: Arc<dyn Hittable> = Arc::new(make_box(
+        Point3::new(130.0, 0.0, 65.0),
+        Point3::new(295.0, 165.0, 230.0),
+        white.clone(),
+    ));
+    let tall_block: Arc<dyn Hittable> = Arc::new(make_box(
+        Point3::new(265.0, 0.0, 295.0),
+        Point3::new(430.0, 330.0, 460.0),
+        white,
+    ));
+    if smoke {
+        let light_smoke = Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0)));
+        let dark_smoke = Arc::new(Isotropic::new(Color::origin()));
+        world.add(Arc::new(ConstantMedium::new(
+            short_block,
+            0.01,
+            light_smoke,
+        )));
+        world.add(Arc::new(ConstantMedium::new(tall_block, 0.01, dark_smoke)));
+    } else {
+        world.add(short_block);
+        world.add(tall_block);
+    }
+
+    let mut camera = Camera::new(
+        1.0,
+        600,
+        200,
+        50,
+        Point3::new(278.0, 278.0, -800.0),
+        Point3::new(278.0, 278.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        0.0,
+        10.0,
+    );
+    camera.set_background(Background::None);
+    Scene {
+        camera,
+        world,
+        lights,
+    }
+}
+
+// The same subdivided icosahedron twice, flat shaded on the left and smooth shaded with vertex
+// normals on the right
+fn icospheres_scene() -> Scene {
+    let mut world = HittableList::empty();
+
+    let ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ground,
+    )));
+
+    let blue = Arc::new(Lambertian::new(Color::new(0.2, 0.3, 0.7)));
+    let gold = Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.05));
+    for (center, smooth, mat) in [
+        (
+            Point3::new(-1.2, 1.0, 0.0),
+            false,
+            blue as Arc<dyn Material>,
+        ),
+        (Point3::new(1.2, 1.0, 0.0), true, gold),
+    ] {
+        let mesh = Arc::new(icosphere(center, 1.0, 2, smooth, mat));
+        for triangle in mesh.triangles() {
+            world.add(triangle);
+        }
+    }
+
+    let camera = Camera::new(
+        16.0 / 9.0,
+        400,
+        100,
+        50,
+        Point3::new(0.0, 2.0, 8.0),
+        Point3::new(0.0, 0.8, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        0.0,
+        10.0,
+    );
+    Scene {
+        camera,
+        world,
+        lights: HittableList::empty(),
+    }
+}
+
+// Sphere approximated by an icosahedron whose faces are split into four, subdivisions times
+fn icosphere(
+    center: Point3,
+    radius: f64,
+    subdivisions: u32,
+    smooth: bool,
+    mat: Arc<dyn Material>,
+) -> TriangleMesh {
+    let t = (1.0 + 5f64.sqrt()) / 2.0;
+    let mut directions: Vec<Vec3> = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|&(x, y, z)| Vec3::new(x, y, z).unit_vector())
+    .collect();
+    let mut faces: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let m = (directions[a as usize] + directions[b as usize]).unit_vector();
+                directions.push(m);
+                directions.len() as u32 - 1
+            })
+        };
+        faces = faces
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let positions = directions.iter().map(|&d| center + radius * d).collect();
+    let normals = if smooth { directions } else { Vec::new() };
+    TriangleMesh::new(positions, normals, Vec::new(), Vec::new(), faces, mat)
+}
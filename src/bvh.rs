@@ -0,0 +1,254 @@
+use std::{cmp::Ordering, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    ray::Ray,
+    sampler::Sampler,
+};
+
+// Number of buckets centroids are binned into when evaluating the surface area heuristic
+const SAH_BUCKETS: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SplitMethod {
+    // Split each node at the object median along the longest centroid axis
+    Median,
+    // Choose the bucketed split that minimises the surface area heuristic cost
+    Sah,
+}
+
+pub(crate) struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub(crate) fn new(list: HittableList, split: SplitMethod) -> Self {
+        let mut objects = list.into_objects();
+        if objects.is_empty() {
+            let empty: Arc<dyn Hittable> = Arc::new(HittableList::empty());
+            return Self {
+                left: empty.clone(),
+                right: empty,
+                bbox: Aabb::empty(),
+            };
+        }
+        Self::build(&mut objects, split)
+    }
+
+    fn build(objects: &mut [Arc<dyn Hittable>], split: SplitMethod) -> Self {
+        let bbox = objects.iter().fold(Aabb::empty(), |bbox, object| {
+            Aabb::surrounding(&bbox, &object.bounding_box())
+        });
+
+        let (left, right): (Arc<dyn Hittable>, Arc<dyn Hittable>) = match objects.len() {
+            1 => (objects[0].clone(), objects[0].clone()),
+            2 => (objects[0].clone(), objects[1].clone()),
+            n => {
+                let centroid_bounds = objects.iter().fold(Aabb::empty(), |bounds, object| {
+                    let c = object.bounding_box().centroid();
+                    Aabb::surrounding(&bounds, &Aabb::from_points(c, c))
+                });
+                let axis = centroid_bounds.longest_axis();
+                let extent = *centroid_bounds.axis_interval(axis);
+
+                let sah_mid = match split {
+                    SplitMethod::Median => None,
+                    SplitMethod::Sah => {
+                        sah_split(objects, |o| o.bounding_box(), &extent, axis, &bbox).map(
+                            |bucket| {
+                                partition(objects, |o| {
+                                    sah_bucket(o.bounding_box().centroid()[axis], &extent) < bucket
+                                })
+                            },
+                        )
+                    }
+                };
+                let mid = sah_mid.unwrap_or_else(|| {
+                    objects
+                        .select_nth_unstable_by(n / 2, |a, b| Self::centroid_compare(a, b, axis));
+                    n / 2
+                });
+
+                let (left, right) = objects.split_at_mut(mid);
+                (
+                    Arc::new(Self::build(left, split)),
+                    Arc::new(Self::build(right, split)),
+                )
+            }
+        };
+
+        Self { left, right, bbox }
+    }
+
+    fn centroid_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize) -> Ordering {
+        let a_centroid = a.bounding_box().centroid()[axis];
+        let b_centroid = b.bounding_box().centroid()[axis];
+        a_centroid.total_cmp(&b_centroid)
+    }
+}
+
+// Index of the surface area heuristic bucket that a centroid coordinate along the split axis
+// falls into, given the extent of all centroids on that axis
+pub(crate) fn sah_bucket(centroid: f64, extent: &Interval) -> usize {
+    let b = (SAH_BUCKETS as f64 * (centroid - extent.min) / extent.size()) as usize;
+    b.min(SAH_BUCKETS - 1)
+}
+
+// Finds the cheapest bucket boundary for splitting items along axis by the surface area
+// heuristic. Items whose sah_bucket is below the returned bucket belong on the left. Returns
+// None when the centroids cannot be separated.
+pub(crate) fn sah_split<T>(
+    items: &[T],
+    bbox_of: impl Fn(&T) -> Aabb,
+    extent: &Interval,
+    axis: usize,
+    bbox: &Aabb,
+) -> Option<usize> {
+    if extent.size() <= 0.0 {
+        return None;
+    }
+
+    let mut counts = [0usize; SAH_BUCKETS];
+    let mut bounds = [Aabb::empty(); SAH_BUCKETS];
+    for item in items {
+        let item_box = bbox_of(item);
+        let b = sah_bucket(item_box.centroid()[axis], extent);
+        counts[b] += 1;
+        bounds[b] = Aabb::surrounding(&bounds[b], &item_box);
+    }
+
+    let mut best: Option<(usize, f64)> = None;
+    for split in 1..SAH_BUCKETS {
+        let (mut left_box, mut right_box) = (Aabb::empty(), Aabb::empty());
+        let (mut left_count, mut right_count) = (0, 0);
+        for b in 0..split {
+            left_box = Aabb::surrounding(&left_box, &bounds[b]);
+            left_count += counts[b];
+        }
+        for b in split..SAH_BUCKETS {
+            right_box = Aabb::surrounding(&right_box, &bounds[b]);
+            right_count += counts[b];
+        }
+        if left_count == 0 || right_count == 0 {
+            continue;
+        }
+
+        let cost = (left_count as f64 * left_box.surface_area()
+            + right_count as f64 * right_box.surface_area())
+            / bbox.surface_area();
+        if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+            best = Some((split, cost));
+        }
+    }
+    best.map(|(split, _)| split)
+}
+
+// Reorders items so that those matching pred come first, returning how many matched
+pub(crate) fn partition<T>(items: &mut [T], mut pred: impl FnMut(&T) -> bool) -> usize {
+    let mut first_false = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(first_false, i);
+            first_false += 1;
+        }
+    }
+    first_false
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> Option<HitRecord> {
+        if !self.bbox.hit(r, ray_t) {
+            return None;
+        }
+
+        let hit_left = self.left.hit(r, ray_t, sampler);
+        let closest = hit_left.as_ref().map_or(ray_t.max, |rec| rec.t);
+        let hit_right = self
+            .right
+            .hit(r, &Interval::new(ray_t.min, closest), sampler);
+        hit_right.or(hit_left)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        bvh::{BvhNode, SplitMethod},
+        color::Color,
+        hittable::Hittable,
+        hittable_list::HittableList,
+        interval::Interval,
+        material::Lambertian,
+        ray::Ray,
+        sampler::Sampler,
+        sphere::Sphere,
+        vec3::{Point3, Vec3},
+    };
+
+    fn random_spheres(sampler: &mut Sampler, n: usize) -> Vec<Arc<Sphere>> {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        (0..n)
+            .map(|_| {
+                Arc::new(Sphere::new(
+                    Vec3::random_range(sampler, -10.0, 10.0),
+                    sampler.random_f64_range(0.1, 1.5),
+                    mat.clone(),
+                ))
+            })
+            .collect()
+    }
+
+    fn assert_matches_flat_list(split: SplitMethod) {
+        let mut sampler = Sampler::new(1);
+        let spheres = random_spheres(&mut sampler, 200);
+        let mut flat = HittableList::empty();
+        let mut list = HittableList::empty();
+        for s in &spheres {
+            flat.add(s.clone());
+            list.add(s.clone());
+        }
+        let bvh = BvhNode::new(list, split);
+
+        for _ in 0..2000 {
+            let r = Ray::new(
+                Point3::random_range(&mut sampler, -15.0, 15.0),
+                Vec3::random_unit_vector(&mut sampler),
+            );
+            let ray_t = Interval::new(0.001, f64::INFINITY);
+            let expected = flat.hit(&r, &ray_t, &mut sampler).map(|rec| rec.t);
+            let actual = bvh.hit(&r, &ray_t, &mut sampler).map(|rec| rec.t);
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn median_bvh_matches_flat_list() {
+        assert_matches_flat_list(SplitMethod::Median);
+    }
+
+    #[test]
+    fn sah_bvh_matches_flat_list() {
+        assert_matches_flat_list(SplitMethod::Sah);
+    }
+
+    #[test]
+    fn empty_bvh_never_hits() {
+        let mut sampler = Sampler::new(0);
+        let bvh = BvhNode::new(HittableList::empty(), SplitMethod::Sah);
+        let r = Ray::new(Point3::origin(), Vec3::new(0.0, 0.0, -1.0));
+        assert!(bvh
+            .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .is_none());
+    }
+}
@@ -1,14 +1,20 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Write},
+    f64::consts::PI,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
 };
 
 use crate::{
-    color::{write_color, Color},
+    color::Color,
+    framebuffer::{Framebuffer, Splats},
     hittable::Hittable,
-    interval::Interval,
+    integrator::{Integrator, RenderContext},
     ray::Ray,
-    utility::{degrees_to_radians, random_f64},
+    sampler::Sampler,
+    utility::degrees_to_radians,
     vec3::{Point3, Vec3},
 };
 
@@ -35,9 +41,51 @@ pub(crate) struct Camera {
     focus_dist: f64,
     defcous_disk_u: Vec3,
     defcous_disk_v: Vec3,
+    // Each ray is cast at a random time in [shutter_open, shutter_close], where moving objects
+    // travel along their paths over times 0 to 1
+    shutter_open: f64,
+    shutter_close: f64,
+    threads: usize,
+    seed: u64,
+    background: Background,
+}
+
+// What a ray sees when it escapes the scene without hitting anything
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Background {
+    // Blend from white at the horizon to light blue overhead
+    Gradient,
+    Solid(Color),
+    // Pure black, leaving emissive objects as the only light sources
+    None,
+}
+
+impl Background {
+    pub(crate) fn color(&self, r: &Ray) -> Color {
+        match self {
+            Background::Gradient => {
+                let unit_direction = r.direction().unit_vector();
+                let a = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+            }
+            Background::Solid(color) => *color,
+            Background::None => Color::origin(),
+        }
+    }
+}
+
+// Edge length in pixels of the square tiles handed out to render workers
+const TILE_SIZE: i64 = 16;
+
+struct Tile {
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         aspect_ratio: f64,
         image_width: i64,
@@ -72,32 +120,199 @@ impl Camera {
             focus_dist,
             defcous_disk_u: Vec3::origin(),
             defcous_disk_v: Vec3::origin(),
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: 0,
+            background: Background::Gradient,
         }
     }
 
-    pub(crate) fn render(&mut self, world: impl Hittable) {
+    pub(crate) fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.aspect_ratio = aspect_ratio;
+    }
+
+    pub(crate) fn set_image_width(&mut self, image_width: i64) {
+        self.image_width = image_width;
+    }
+
+    pub(crate) fn set_samples_per_pixel(&mut self, samples_per_pixel: i64) {
+        self.samples_per_pixel = samples_per_pixel;
+    }
+
+    pub(crate) fn set_max_depth(&mut self, max_depth: i64) {
+        self.max_depth = max_depth;
+    }
+
+    pub(crate) fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    pub(crate) fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+
+    pub(crate) fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    // lights, when given, are emitters the integrator may sample directly
+    pub(crate) fn render(
+        &mut self,
+        world: &dyn Hittable,
+        lights: Option<&dyn Hittable>,
+        integrator: &dyn Integrator,
+    ) -> Framebuffer {
         self.initialize();
+        let splats = Splats::new(self.image_width as usize, self.image_height as usize);
+        let context = RenderContext {
+            world,
+            lights,
+            background: self.background,
+            max_depth: self.max_depth,
+            camera: self,
+            splats: &splats,
+        };
+
+        let tiles = self.tiles();
+        let next_tile = AtomicUsize::new(0);
+        let mut framebuffer =
+            Framebuffer::new(self.image_width as usize, self.image_height as usize);
+
+        thread::scope(|s| {
+            let (tx, rx) = mpsc::channel();
+            for _ in 0..self.threads.min(tiles.len()) {
+                let tx = tx.clone();
+                let (cam, tiles, next_tile, context) = (&*self, &tiles, &next_tile, &context);
+                s.spawn(move || loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    let Some(tile) = tiles.get(index) else {
+                        break;
+                    };
+                    tx.send((index, cam.render_tile(tile, context, integrator)))
+                        .unwrap();
+                });
+            }
+            drop(tx);
+
+            for (done, (index, pixels)) in rx.iter().enumerate() {
+                println!("\rTiles remaining: {}", tiles.len() - done - 1);
+                let tile = &tiles[index];
+                framebuffer.write_block(
+                    tile.x0 as usize,
+                    tile.y0 as usize,
+                    (tile.x1 - tile.x0) as usize,
+                    &pixels,
+                );
+            }
+        });
 
-        let f = File::create("image.ppm").unwrap();
-        let mut buffer = BufWriter::new(f);
-        buffer
-            .write_all(
-                format!("P3\n{0} {1}\n255\n", self.image_width, self.image_height).as_bytes(),
-            )
-            .unwrap();
-
-        for j in 0..self.image_height {
-            println!("\rScanlines remaining: {}", self.image_height - j);
-            for i in 0..self.image_width {
+        let splats = splats.into_framebuffer();
+        for (index, &splat) in splats.pixels().iter().enumerate() {
+            let (x, y) = (index % splats.width(), index / splats.width());
+            framebuffer.add(x, y, splat * self.pixel_samples_scale);
+        }
+
+        println!("\rDone!\n");
+        framebuffer
+    }
+
+    // The density, per unit solid angle, with which the camera's rays from origin on the lens
+    // take direction, over the whole image rather than one pixel. Zero outside the image. Every
+    // way of building a path picks its lens point with lens_pdf, so only this density differs
+    // between them.
+    pub(crate) fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.film_pixel(origin, direction).is_none() {
+            return 0.0;
+        }
+        let cosine = direction.unit_vector().dot(&-self.w);
+        let film_area = (self.image_width * self.image_height) as f64
+            * self.pixel_delta_u.length()
+            * self.pixel_delta_v.length();
+        self.focus_dist * self.focus_dist / (film_area * cosine * cosine * cosine)
+    }
+
+    // The density per unit area of the points the camera's rays start from, uniform over the
+    // lens, or 1 for a pinhole, whose rays all start at its center
+    pub(crate) fn lens_pdf(&self) -> f64 {
+        if self.defocus_angle <= 0.0 {
+            return 1.0;
+        }
+        1.0 / (PI * self.defcous_disk_u.length_squared())
+    }
+
+    // Joins p to the camera: a point sampled on the lens with lens_pdf, and the pixel the ray
+    // from it towards p passes through, if any
+    pub(crate) fn connect(
+        &self,
+        p: &Point3,
+        sampler: &mut Sampler,
+    ) -> Option<(Point3, (usize, usize))> {
+        let lens = if self.defocus_angle <= 0.0 {
+            self.center
+        } else {
+            self.defocus_disk_sample(sampler)
+        };
+        let pixel = self.film_pixel(&lens, &(*p - lens))?;
+        Some((lens, pixel))
+    }
+
+    // The pixel a ray from origin on the lens along direction passes through. The pixels lie on
+    // the plane of focus, focus_dist in front of the lens.
+    fn film_pixel(&self, origin: &Point3, direction: &Vec3) -> Option<(usize, usize)> {
+        let cosine = direction.dot(&-self.w);
+        if cosine <= 0.0 {
+            return None;
+        }
+        let on_film = *origin + (self.focus_dist / cosine) * *direction;
+        let offset = on_film - (self.pixel00_loc - 0.5 * (self.pixel_delta_u + self.pixel_delta_v));
+        let x = offset.dot(&self.pixel_delta_u) / self.pixel_delta_u.length_squared();
+        let y = offset.dot(&self.pixel_delta_v) / self.pixel_delta_v.length_squared();
+        if x < 0.0 || y < 0.0 || x >= self.image_width as f64 || y >= self.image_height as f64 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    fn tiles(&self) -> Vec<Tile> {
+        let mut tiles = Vec::new();
+        for y0 in (0..self.image_height).step_by(TILE_SIZE as usize) {
+            for x0 in (0..self.image_width).step_by(TILE_SIZE as usize) {
+                tiles.push(Tile {
+                    x0,
+                    y0,
+                    x1: (x0 + TILE_SIZE).min(self.image_width),
+                    y1: (y0 + TILE_SIZE).min(self.image_height),
+                });
+            }
+        }
+        tiles
+    }
+
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        context: &RenderContext,
+        integrator: &dyn Integrator,
+    ) -> Vec<Color> {
+        let mut pixels = Vec::with_capacity(((tile.x1 - tile.x0) * (tile.y1 - tile.y0)) as usize);
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                let mut sampler = Sampler::for_pixel(self.seed, i, j);
                 let mut pixel_color = Color::origin();
                 for _ in 0..self.samples_per_pixel {
-                    let r: Ray = self.get_ray(i, j);
-                    pixel_color += Camera::ray_color(&r, &world, self.max_depth);
+                    let r: Ray = self.get_ray(i, j, &mut sampler);
+                    pixel_color += integrator.ray_color(r, context, &mut sampler);
                 }
-                write_color(&mut buffer, pixel_color * self.pixel_samples_scale);
+                pixels.push(pixel_color * self.pixel_samples_scale);
             }
         }
-        println!("\rDone!\n");
+        pixels
     }
 
     fn initialize(&mut self) {
@@ -139,10 +354,10 @@ impl Camera {
         self.defcous_disk_v = self.v * defocus_radius;
     }
 
-    fn get_ray(&self, i: i64, j: i64) -> Ray {
+    fn get_ray(&self, i: i64, j: i64, sampler: &mut Sampler) -> Ray {
         // Construct a camera ray originating from the defocus disk directed at a randomly
         // sampled point around the pixel location i, j.
-        let offset = Camera::sample_square();
+        let offset = Camera::sample_square(sampler);
         let pixel_sample = self.pixel00_loc
             + ((i as f64 + offset.x()) * self.pixel_delta_u)
             + ((j as f64 + offset.y()) * self.pixel_delta_v);
@@ -150,36 +365,20 @@ impl Camera {
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(sampler)
         };
         let ray_direction = pixel_sample - ray_origin;
-        Ray::new(ray_origin, ray_direction)
+        let ray_time =
+            self.shutter_open + (self.shutter_close - self.shutter_open) * sampler.random_f64();
+        Ray::with_time(ray_origin, ray_direction, ray_time)
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
+    fn defocus_disk_sample(&self, sampler: &mut Sampler) -> Point3 {
+        let p = Vec3::random_in_unit_disk(sampler);
         self.center + (p.x() * self.defcous_disk_u) + (p.y() * self.defcous_disk_v)
     }
 
-    fn sample_square() -> Vec3 {
-        Vec3::new(random_f64() - 0.5, random_f64() - 0.5, 0.0)
-    }
-
-    fn ray_color(r: &Ray, world: &dyn Hittable, depth: i64) -> Color {
-        if depth < 0 {
-            return Color::origin();
-        }
-        if let Some(rec) = world.hit(r, &Interval::new(0.001, f64::INFINITY)) {
-            if let Some(m) = &rec.mat {
-                if let Some((s, a)) = m.scatter(r, &rec) {
-                    return a * Self::ray_color(&s, world, depth - 1);
-                }
-            }
-            let direction = rec.normal + Vec3::random_unit_vector();
-            return 0.6 * Self::ray_color(&Ray::new(rec.p, direction), world, depth - 1);
-        }
-        let unit_direction = r.direction().unit_vector();
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+    fn sample_square(sampler: &mut Sampler) -> Vec3 {
+        Vec3::new(sampler.random_f64() - 0.5, sampler.random_f64() - 0.5, 0.0)
     }
 }
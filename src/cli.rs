@@ -0,0 +1,412 @@
+use std::path::PathBuf;
+
+use crate::{
+    bvh::SplitMethod,
+    integrator::{DebugOutput, Sampling},
+    output::ImageFormat,
+};
+
+pub(crate) const USAGE: &str = "\
+Usage: rays [OPTIONS]
+
+Renders a scene with a Monte Carlo path tracer.
+
+Scene:
+  -s, --scene <FILE>          Load a scene description or glTF (.gltf, .glb) file
+  -b, --builtin <NAME>        Render a built-in scene [default: random-spheres]
+                              (random-spheres, bouncing-spheres, simple-light,
+                              cornell-box, cornell-smoke, icospheres)
+
+Image:
+  -w, --width <PIXELS>        Image width, overriding the scene's camera
+  -a, --aspect <RATIO>        Aspect ratio as a number or W:H, e.g. 1.5 or 16:9
+  -n, --samples <COUNT>       Samples per pixel
+  -d, --max-depth <COUNT>     Maximum number of ray bounces
+      --roulette <COUNT>      Bounces before dim paths may be ended at random, or off
+                              [default: 3]
+      --seed <SEED>           Seed for scene generation and sampling [default: 0]
+  -j, --threads <COUNT>       Render worker threads [default: available cores]
+      --bvh <METHOD>          Acceleration structure: sah, median or off [default: sah]
+
+Rendering:
+  -i, --integrator <NAME>     path (path tracing), bdpt (bidirectional path tracing),
+                              direct (direct lighting only), ao (ambient occlusion), or a
+                              view of the first hit: normals, depth, uv or material
+                              [default: path]
+      --sampling <METHOD>     How path tracing finds lights: power or balance for
+                              multiple importance sampling, mixture, or bsdf to sample
+                              only materials [default: power]
+      --ao-distance <DIST>    How far ambient occlusion looks for occluders
+                              [default: unlimited]
+
+Output:
+  -o, --output <FILE>         Output file [default: image.ppm]
+  -f, --format <FORMAT>       ppm, ppm-binary, png, png16, hdr or exr
+                              [default: guessed from the output extension]
+
+  -h, --help                  Print this help
+";
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum SceneSource {
+    File(PathBuf),
+    Builtin(String),
+}
+
+// The rendering algorithm chosen with --integrator
+#[derive(Debug, PartialEq)]
+pub(crate) enum IntegratorChoice {
+    Path,
+    Bidirectional,
+    Direct,
+    AmbientOcclusion,
+    Debug(DebugOutput),
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Options {
+    pub(crate) scene: SceneSource,
+    pub(crate) image_width: Option<i64>,
+    pub(crate) aspect_ratio: Option<f64>,
+    pub(crate) samples_per_pixel: Option<i64>,
+    pub(crate) max_depth: Option<i64>,
+    // None lets every path run to max_depth
+    pub(crate) roulette_depth: Option<i64>,
+    pub(crate) seed: u64,
+    pub(crate) threads: Option<usize>,
+    // None renders from the flat object list
+    pub(crate) split: Option<SplitMethod>,
+    pub(crate) integrator: IntegratorChoice,
+    pub(crate) sampling: Sampling,
+    pub(crate) ao_distance: f64,
+    pub(crate) output: PathBuf,
+    pub(crate) format: ImageFormat,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Command {
+    Render(Options),
+    Help,
+}
+
+// Parses the arguments following the program name. Errors are human readable messages.
+pub(crate) fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut scene_file = None;
+    let mut builtin = None;
+    let mut image_width = None;
+    let mut aspect_ratio = None;
+    let mut samples_per_pixel = None;
+    let mut max_depth = None;
+    let mut roulette_depth = Some(3);
+    let mut seed = 0;
+    let mut threads = None;
+    let mut split = Some(SplitMethod::Sah);
+    let mut integrator = IntegratorChoice::Path;
+    let mut sampling = Sampling::Power;
+    let mut ao_distance = f64::INFINITY;
+    let mut output = None;
+    let mut format = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Accept both "--flag value" and "--flag=value"
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        if flag == "-h" || flag == "--help" {
+            return Ok(Command::Help);
+        }
+
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{flag} requires a value"))
+        };
+        match flag.as_str() {
+            "-s" | "--scene" => scene_file = Some(PathBuf::from(value()?)),
+            "-b" | "--builtin" => builtin = Some(value()?),
+            "-w" | "--width" => image_width = Some(positive(&flag, &value()?)?),
+            "-a" | "--aspect" => aspect_ratio = Some(parse_aspect(&value()?)?),
+            "-n" | "--samples" => samples_per_pixel = Some(positive(&flag, &value()?)?),
+            "-d" | "--max-depth" => max_depth = Some(positive(&flag, &value()?)?),
+            "--roulette" => {
+                let v = value()?;
+                roulette_depth = match v.as_str() {
+                    "off" => None,
+                    _ => Some(v.parse().ok().filter(|&n: &i64| n >= 0).ok_or_else(|| {
+                        format!("{flag} expects a non-negative integer or off, found '{v}'")
+                    })?),
+                }
+            }
+            "--seed" => {
+                let v = value()?;
+                seed = v
+                    .parse()
+                    .map_err(|_| format!("{flag} expects a non-negative integer, found '{v}'"))?;
+            }
+            "-j" | "--threads" => threads = Some(positive(&flag, &value()?)? as usize),
+            "--bvh" => {
+                split = match value()?.as_str() {
+                    "sah" => Some(SplitMethod::Sah),
+                    "median" => Some(SplitMethod::Median),
+                    "off" => None,
+                    other => {
+                        return Err(format!(
+                            "{flag} expects sah, median or off, found '{other}'"
+                        ))
+                    }
+                }
+            }
+            "-i" | "--integrator" => {
+                integrator = match value()?.as_str() {
+                    "path" => IntegratorChoice::Path,
+                    "bdpt" => IntegratorChoice::Bidirectional,
+                    "direct" => IntegratorChoice::Direct,
+                    "ao" => IntegratorChoice::AmbientOcclusion,
+                    "normals" => IntegratorChoice::Debug(DebugOutput::Normals),
+                    "depth" => IntegratorChoice::Debug(DebugOutput::Depth),
+                    "uv" => IntegratorChoice::Debug(DebugOutput::Uv),
+                    "material" => IntegratorChoice::Debug(DebugOutput::Material),
+                    other => {
+                        return Err(format!(
+                            "{flag} expects path, bdpt, direct, ao, normals, depth, uv or material, found '{other}'"
+                        ))
+                    }
+                }
+            }
+            "--ao-distance" => {
+                let v = value()?;
+                ao_distance = v
+                    .parse()
+                    .ok()
+                    .filter(|&d: &f64| d > 0.0)
+                    .ok_or_else(|| format!("{flag} expects a positive distance, found '{v}'"))?;
+            }
+            "--sampling" => {
+                sampling = match value()?.as_str() {
+                    "power" => Sampling::Power,
+                    "balance" => Sampling::Balance,
+                    "mixture" => Sampling::Mixture,
+                    "bsdf" => Sampling::Bsdf,
+                    other => {
+                        return Err(format!(
+                            "{flag} expects power, balance, mixture or bsdf, found '{other}'"
+                        ))
+                    }
+                }
+            }
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "-f" | "--format" => {
+                let v = value()?;
+                format = Some(ImageFormat::from_name(&v).ok_or_else(|| {
+                    format!(
+                        "{flag} expects one of ppm, ppm-binary, png, png16, hdr, exr, found '{v}'"
+                    )
+                })?);
+            }
+            _ => return Err(format!("unrecognised argument '{flag}'")),
+        }
+    }
+
+    let scene = match (scene_file, builtin) {
+        (Some(_), Some(_)) => return Err(String::from("--scene and --builtin are exclusive")),
+        (Some(path), None) => SceneSource::File(path),
+        (None, name) => {
+            SceneSource::Builtin(name.unwrap_or_else(|| String::from("random-spheres")))
+        }
+    };
+    let output = output.unwrap_or_else(|| PathBuf::from("image.ppm"));
+    let format = match format {
+        Some(format) => format,
+        None => match output.extension() {
+            None => ImageFormat::PpmAscii,
+            Some(_) => ImageFormat::from_path(&output).ok_or_else(|| {
+                format!(
+                    "cannot tell the format of '{}' from its extension, use --format",
+                    output.display()
+                )
+            })?,
+        },
+    };
+
+    Ok(Command::Render(Options {
+        scene,
+        image_width,
+        aspect_ratio,
+        samples_per_pixel,
+        max_depth,
+        roulette_depth,
+        seed,
+        threads,
+        split,
+        integrator,
+        sampling,
+        ao_distance,
+        output,
+        format,
+    }))
+}
+
+fn positive(flag: &str, value: &str) -> Result<i64, String> {
+    match value.parse::<i64>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "{flag} expects a positive integer, found '{value}'"
+        )),
+    }
+}
+
+fn parse_aspect(value: &str) -> Result<f64, String> {
+    let ratio = match value.split_once([':', '/']) {
+        Some((w, h)) => w
+            .parse::<f64>()
+            .ok()
+            .zip(h.parse::<f64>().ok())
+            .map(|(w, h)| w / h),
+        None => value.parse().ok(),
+    };
+    match ratio {
+        Some(r) if r.is_finite() && r > 0.0 => Ok(r),
+        _ => Err(format!(
+            "--aspect expects a positive ratio such as 1.5 or 16:9, found '{value}'"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        bvh::SplitMethod,
+        cli::{parse_args, Command, IntegratorChoice, Options, SceneSource},
+        integrator::{DebugOutput, Sampling},
+        output::ImageFormat,
+    };
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Render(options)) => options,
+            other => panic!("expected render options, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(
+            options(&[]),
+            Options {
+                scene: SceneSource::Builtin(String::from("random-spheres")),
+                image_width: None,
+                aspect_ratio: None,
+                samples_per_pixel: None,
+                max_depth: None,
+                roulette_depth: Some(3),
+                seed: 0,
+                threads: None,
+                split: Some(SplitMethod::Sah),
+                integrator: IntegratorChoice::Path,
+                sampling: Sampling::Power,
+                ao_distance: f64::INFINITY,
+                output: PathBuf::from("image.ppm"),
+                format: ImageFormat::PpmAscii,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_every_flag() {
+        let o = options(&[
+            "--scene",
+            "room.scene",
+            "-w",
+            "640",
+            "--aspect=16:9",
+            "-n",
+            "32",
+            "--max-depth",
+            "8",
+            "--roulette=off",
+            "--seed=99",
+            "-j",
+            "3",
+            "--bvh",
+            "off",
+            "--sampling=bsdf",
+            "-i",
+            "normals",
+            "--ao-distance",
+            "2.5",
+            "-o",
+            "out/render.exr",
+        ]);
+        assert_eq!(o.scene, SceneSource::File(PathBuf::from("room.scene")));
+        assert_eq!(o.image_width, Some(640));
+        assert_eq!(o.aspect_ratio, Some(16.0 / 9.0));
+        assert_eq!(o.samples_per_pixel, Some(32));
+        assert_eq!(o.max_depth, Some(8));
+        assert_eq!(o.roulette_depth, None);
+        assert_eq!(o.seed, 99);
+        assert_eq!(o.threads, Some(3));
+        assert_eq!(o.split, None);
+        assert_eq!(o.sampling, Sampling::Bsdf);
+        assert_eq!(o.integrator, IntegratorChoice::Debug(DebugOutput::Normals));
+        assert_eq!(o.ao_distance, 2.5);
+        assert_eq!(o.format, ImageFormat::Exr);
+
+        let o = options(&["-o", "render.png", "--format", "png16", "-a", "1.5"]);
+        assert_eq!(o.format, ImageFormat::Png16);
+        assert_eq!(o.aspect_ratio, Some(1.5));
+    }
+
+    #[test]
+    fn help() {
+        assert_eq!(parse(&["-w", "10", "--help"]), Ok(Command::Help));
+    }
+
+    #[test]
+    fn validation_errors() {
+        let error = |args: &[&str]| parse(args).unwrap_err();
+        assert_eq!(
+            error(&["-w", "0"]),
+            "-w expects a positive integer, found '0'"
+        );
+        assert_eq!(error(&["--samples"]), "--samples requires a value");
+        assert_eq!(
+            error(&["--aspect", "16:0"]),
+            "--aspect expects a positive ratio such as 1.5 or 16:9, found '16:0'"
+        );
+        assert_eq!(
+            error(&["--frobnicate"]),
+            "unrecognised argument '--frobnicate'"
+        );
+        assert_eq!(
+            error(&["-o", "render.tga"]),
+            "cannot tell the format of 'render.tga' from its extension, use --format"
+        );
+        assert_eq!(
+            error(&["-s", "a.scene", "-b", "random-spheres"]),
+            "--scene and --builtin are exclusive"
+        );
+        assert_eq!(
+            error(&["--seed", "-1"]),
+            "--seed expects a non-negative integer, found '-1'"
+        );
+        assert_eq!(
+            error(&["--roulette", "never"]),
+            "--roulette expects a non-negative integer or off, found 'never'"
+        );
+        assert_eq!(
+            error(&["--integrator", "whitted"]),
+            "--integrator expects path, bdpt, direct, ao, normals, depth, uv or material, found 'whitted'"
+        );
+    }
+}
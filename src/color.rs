@@ -1,8 +1,5 @@
 use crate::interval::Interval;
 use crate::vec3::Vec3;
-use std::fs::File;
-use std::io::BufWriter;
-use std::io::Write;
 
 pub(crate) type Color = Vec3;
 
@@ -14,17 +11,33 @@ fn linear_to_gamma(linear_component: f64) -> f64 {
     }
 }
 
-pub(crate) fn write_color(buff: &mut BufWriter<File>, pixel_color: Color) {
-    let r = linear_to_gamma(pixel_color.x());
-    let g = linear_to_gamma(pixel_color.y());
-    let b = linear_to_gamma(pixel_color.z());
+// Decodes an sRGB encoded component in [0, 1] to linear intensity
+pub(crate) fn srgb_to_linear(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
 
+pub(crate) fn to_bytes(pixel_color: Color) -> [u8; 3] {
     // Translate the [0,1] component values to the byte range [0, 255]
     let intensity = Interval::new(0.000, 0.999);
-    let rbyte = (256.0 * intensity.clamp(r)) as i64;
-    let gbyte = (256.0 * intensity.clamp(g)) as i64;
-    let bbyte = (256.0 * intensity.clamp(b)) as i64;
+    let quantize = |c: f64| (256.0 * intensity.clamp(linear_to_gamma(c))) as u8;
+    [
+        quantize(pixel_color.x()),
+        quantize(pixel_color.y()),
+        quantize(pixel_color.z()),
+    ]
+}
 
-    buff.write_all(format!("{rbyte} {gbyte} {bbyte}\n").as_bytes())
-        .unwrap();
+pub(crate) fn to_words(pixel_color: Color) -> [u16; 3] {
+    // Translate the [0,1] component values to the 16 bit range [0, 65535]
+    let intensity = Interval::new(0.0, 1.0);
+    let quantize = |c: f64| (65535.0 * intensity.clamp(linear_to_gamma(c))).round() as u16;
+    [
+        quantize(pixel_color.x()),
+        quantize(pixel_color.y()),
+        quantize(pixel_color.z()),
+    ]
 }
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+// A volume of uniform density filling a boundary object, such as fog in a room or smoke in a
+// box. Rays passing through scatter at an exponentially distributed distance, and the phase
+// function (usually Isotropic) picks where they go next. The boundary must be convex: a ray is
+// taken to be inside from where it first enters until where it next leaves.
+pub(crate) struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub(crate) fn new(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> Option<HitRecord> {
+        let everywhere = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+        let entry = self.boundary.hit(r, &everywhere, sampler)?;
+        let exit =
+            self.boundary
+                .hit(r, &Interval::new(entry.t + 0.0001, f64::INFINITY), sampler)?;
+
+        let t_enter = entry.t.max(ray_t.min).max(0.0);
+        let t_exit = exit.t.min(ray_t.max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = r.direction().length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * sampler.random_f64().ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let mut rec = HitRecord::new();
+        rec.t = t_enter + hit_distance / ray_length;
+        rec.p = r.at(rec.t);
+        // A scattering point has no surface, so the normal is arbitrary
+        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.in_medium = true;
+        rec.mat = Some(self.phase_function.clone());
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        color::Color,
+        constant_medium::ConstantMedium,
+        hittable::Hittable,
+        hittable_list::HittableList,
+        interval::Interval,
+        material::Isotropic,
+        ray::Ray,
+        sampler::Sampler,
+        sphere::Sphere,
+        vec3::{Point3, Vec3},
+    };
+
+    #[test]
+    fn scatters_inside_at_the_expected_rate() {
+        let mut sampler = Sampler::new(0);
+        let phase = Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0)));
+        let boundary = Arc::new(Sphere::new(Point3::origin(), 1.0, phase.clone()));
+        let medium = ConstantMedium::new(boundary, 0.5, phase);
+
+        // Crossing the sphere's diameter of 2 at density 0.5 scatters 1 - e^-1 of the rays
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        let rays = 10_000;
+        let mut scattered = 0;
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        for _ in 0..rays {
+            if let Some(rec) = medium.hit(&r, &ray_t, &mut sampler) {
+                assert!(rec.t >= 2.0 && rec.t <= 3.0);
+                scattered += 1;
+            }
+        }
+        let expected = 1.0 - (-1.0f64).exp();
+        assert!((scattered as f64 / rays as f64 - expected).abs() < 0.02);
+
+        let miss = Ray::new(Point3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(medium.hit(&miss, &ray_t, &mut sampler).is_none());
+    }
+
+    #[test]
+    fn media_in_line_scatter_independently() {
+        let phase = Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0)));
+        let mut world = HittableList::empty();
+        for z in [0.0, -3.0] {
+            let boundary = Arc::new(Sphere::new(Point3::new(0.0, 0.0, z), 1.0, phase.clone()));
+            world.add(Arc::new(ConstantMedium::new(boundary, 0.5, phase.clone())));
+        }
+
+        // A ray through both spheres' diameters passes unscattered e^-2 of the time
+        let mut sampler = Sampler::new(0);
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let rays = 10_000;
+        let passed = (0..rays)
+            .filter(|_| world.hit(&r, &ray_t, &mut sampler).is_none())
+            .count();
+        let expected = (-2.0f64).exp();
+        assert!((passed as f64 / rays as f64 - expected).abs() < 0.02);
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Mutex;
+
+use crate::color::Color;
+
+// Linear radiance for every pixel of a rendered image, stored row by row from the top left
+pub(crate) struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::origin(); width * height],
+        }
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> usize {
+        self.height
+    }
+
+    pub(crate) fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    // Copies a row-major block of pixels whose top left corner lands at (x, y)
+    pub(crate) fn write_block(&mut self, x: usize, y: usize, block_width: usize, block: &[Color]) {
+        for (row, pixels) in block.chunks(block_width).enumerate() {
+            let start = (y + row) * self.width + x;
+            self.pixels[start..start + pixels.len()].copy_from_slice(pixels);
+        }
+    }
+
+    pub(crate) fn add(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] += color;
+    }
+}
+
+// Light that render threads add to arbitrary pixels, such as along paths traced out of lights
+// and joined straight to the camera. It is summed over all samples, not averaged.
+pub(crate) struct Splats {
+    framebuffer: Mutex<Framebuffer>,
+}
+
+impl Splats {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self {
+            framebuffer: Mutex::new(Framebuffer::new(width, height)),
+        }
+    }
+
+    // Adds a batch of (x, y, color) splats at once, to lock the buffer less often
+    pub(crate) fn add(&self, splats: &[(usize, usize, Color)]) {
+        let mut framebuffer = self.framebuffer.lock().unwrap();
+        for &(x, y, color) in splats {
+            framebuffer.add(x, y, color);
+        }
+    }
+
+    pub(crate) fn into_framebuffer(self) -> Framebuffer {
+        self.framebuffer.into_inner().unwrap()
+    }
+}
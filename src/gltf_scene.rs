@@ -0,0 +1,559 @@
+// glTF 2.0 import for .gltf and .glb files. Each mesh is built once, every primitive becoming a
+// TriangleMesh with its own MeshBvh, and every node of the default scene that uses it places an
+// instance through the node's world transform. Primitives with emissive materials are also
+// placed among the scene's lights. The first perspective camera found while walking the
+// hierarchy becomes the scene's camera; without one the camera frames the whole world.
+//
+// Metallic-roughness materials are mapped onto the crate's materials:
+//   emissive > 0           DiffuseLight emitting emissiveFactor * emissiveStrength
+//   transmission > 0       Dielectric with the KHR_materials_ior index (1.5 by default)
+//   metallic >= 0.5        Metal with the base color as albedo and roughness as fuzz
+//   otherwise              Lambertian with the base color as albedo
+// A base color texture becomes an ImageTexture tinted by the base color, read through the
+// primitive's first set of texture coordinates with its sampler's wrap modes and magnification
+// filter, and any KHR_texture_transform.
+
+use std::{collections::HashMap, error::Error, fmt, path::Path, sync::Arc};
+
+use gltf::{
+    camera::Projection,
+    image,
+    mesh::Mode,
+    texture::{MagFilter, TextureTransform, WrappingMode},
+    Document, Node,
+};
+
+use crate::{
+    bvh::{BvhNode, SplitMethod},
+    camera::Camera,
+    color::{srgb_to_linear, Color},
+    hittable::Hittable,
+    hittable_list::HittableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    matrix::Matrix,
+    mesh_bvh::MeshBvh,
+    scene::Scene,
+    texture::{Filter, ImageTexture, SolidColor, Texture, UvTransform, WrapMode},
+    transform::{Transform, Transformed},
+    triangle::{TriangleMesh, Uv},
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub(crate) enum GltfError {
+    Import(gltf::Error),
+    // A triangle primitive whose data cannot make a mesh, such as indices past its vertices
+    Primitive {
+        mesh: usize,
+        primitive: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Import(e) => write!(f, "{e}"),
+            GltfError::Primitive {
+                mesh,
+                primitive,
+                message,
+            } => write!(f, "mesh {mesh}, primitive {primitive}: {message}"),
+        }
+    }
+}
+
+impl Error for GltfError {}
+
+impl From<gltf::Error> for GltfError {
+    fn from(e: gltf::Error) -> Self {
+        GltfError::Import(e)
+    }
+}
+
+pub(crate) fn load_gltf(path: &Path) -> Result<Scene, GltfError> {
+    let (document, buffers, images) = gltf::import(path)?;
+    build_scene(&document, &buffers, &images)
+}
+
+struct Importer<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [image::Data],
+    materials: HashMap<Option<usize>, Arc<dyn Material>>,
+    textures: HashMap<usize, Arc<dyn Texture>>,
+    // Shared by every node that instances the mesh
+    meshes: HashMap<usize, Mesh>,
+    world: HittableList,
+    lights: HittableList,
+    camera: Option<Camera>,
+}
+
+#[derive(Clone)]
+struct Mesh {
+    // None for meshes with nothing to render
+    object: Option<Arc<dyn Hittable>>,
+    // The primitives that emit light
+    emitters: Vec<Arc<dyn Hittable>>,
+}
+
+fn build_scene(
+    document: &Document,
+    buffers: &[gltf::buffer::Data],
+    images: &[image::Data],
+) -> Result<Scene, GltfError> {
+    let mut importer = Importer {
+        buffers,
+        images,
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        meshes: HashMap::new(),
+        world: HittableList::empty(),
+        lights: HittableList::empty(),
+        camera: None,
+    };
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        for node in scene.nodes() {
+            importer.visit(&node, &Matrix::identity())?;
+        }
+    }
+
+    let camera = match importer.camera {
+        Some(camera) => camera,
+        None => framing_camera(&importer.world),
+    };
+    Ok(Scene {
+        camera,
+        world: importer.world,
+        lights: importer.lights,
+    })
+}
+
+impl Importer<'_> {
+    fn visit(&mut self, node: &Node, parent: &Matrix) -> Result<(), GltfError> {
+        let local = node
+            .transform()
+            .matrix()
+            .map(|column| column.map(f64::from));
+        let matrix = *parent * Matrix::from_columns(local);
+
+        if let Some(mesh) = node.mesh() {
+            let mesh = match self.meshes.get(&mesh.index()) {
+                Some(mesh) => mesh.clone(),
+                None => {
+                    let built = self.build_mesh(&mesh)?;
+                    self.meshes.insert(mesh.index(), built.clone());
+                    built
+                }
+            };
+            // A singular transform, such as a zero scale, leaves nothing visible
+            if let Some(transform) = Transform::new(matrix) {
+                let place = |object: Arc<dyn Hittable>| -> Arc<dyn Hittable> {
+                    if transform.is_identity() {
+                        object
+                    } else {
+                        Arc::new(Transformed::new(object, transform))
+                    }
+                };
+                if let Some(object) = mesh.object {
+                    self.world.add(place(object));
+                }
+                for emitter in mesh.emitters {
+                    self.lights.add(place(emitter));
+                }
+            }
+        }
+        if let (None, Some(camera)) = (&self.camera, node.camera()) {
+            if let Projection::Perspective(perspective) = camera.projection() {
+                let lookfrom = matrix.transform_point(Point3::origin());
+                let forward = matrix.transform_vector(Vec3::new(0.0, 0.0, -1.0));
+                let mut camera = default_camera(
+                    lookfrom,
+                    lookfrom + forward,
+                    matrix.transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+                    perspective.yfov().to_degrees() as f64,
+                );
+                if let Some(aspect_ratio) = perspective.aspect_ratio() {
+                    camera.set_aspect_ratio(aspect_ratio as f64);
+                }
+                self.camera = Some(camera);
+            }
+        }
+
+        for child in node.children() {
+            self.visit(&child, &matrix)?;
+        }
+        Ok(())
+    }
+
+    fn build_mesh(&mut self, mesh: &gltf::Mesh) -> Result<Mesh, GltfError> {
+        let mut parts: Vec<Arc<dyn Hittable>> = Vec::new();
+        let mut emitters = Vec::new();
+        for primitive in mesh.primitives() {
+            let part =
+                self.build_primitive(&primitive)
+                    .map_err(|message| GltfError::Primitive {
+                        mesh: mesh.index(),
+                        primitive: primitive.index(),
+                        message,
+                    })?;
+            if let Some(part) = part {
+                if emission(&primitive.material()).is_some() {
+                    emitters.push(part.clone());
+                }
+                parts.push(part);
+            }
+        }
+        let object: Option<Arc<dyn Hittable>> = match parts.len() {
+            0 => None,
+            1 => parts.pop(),
+            _ => {
+                let mut list = HittableList::empty();
+                for part in parts {
+                    list.add(part);
+                }
+                Some(Arc::new(BvhNode::new(list, SplitMethod::Sah)))
+            }
+        };
+        Ok(Mesh { object, emitters })
+    }
+
+    // None for points and lines, which have no surface to render
+    fn build_primitive(
+        &mut self,
+        primitive: &gltf::Primitive,
+    ) -> Result<Option<Arc<dyn Hittable>>, String> {
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let positions: Vec<Point3> = reader
+            .read_positions()
+            .ok_or("no vertex positions")?
+            .map(|[x, y, z]| Point3::new(x as f64, y as f64, z as f64))
+            .collect();
+        let normals: Vec<Vec3> = match reader.read_normals() {
+            Some(normals) => normals
+                .map(|[x, y, z]| Vec3::new(x as f64, y as f64, z as f64))
+                .collect(),
+            None => Vec::new(),
+        };
+        // glTF puts the texture origin at the top left, the crate at the bottom left
+        let uvs: Vec<Uv> = match reader.read_tex_coords(0) {
+            Some(uvs) => uvs
+                .into_f32()
+                .map(|[u, v]| (u as f64, 1.0 - v as f64))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let count = positions.len() as u32;
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..count).collect(),
+        };
+        let faces: Vec<[u32; 3]> = match primitive.mode() {
+            Mode::Triangles => indices
+                .chunks_exact(3)
+                .map(|f| [f[0], f[1], f[2]])
+                .collect(),
+            // Alternate the winding so every triangle of the strip faces the same way
+            Mode::TriangleStrip => indices
+                .windows(3)
+                .enumerate()
+                .map(|(i, f)| match i % 2 {
+                    0 => [f[0], f[1], f[2]],
+                    _ => [f[1], f[0], f[2]],
+                })
+                .collect(),
+            Mode::TriangleFan => indices
+                .windows(2)
+                .skip(1)
+                .map(|f| [indices[0], f[0], f[1]])
+                .collect(),
+            _ => return Ok(None),
+        };
+        if faces.is_empty() {
+            return Err(String::from("no triangles"));
+        }
+        if let Some(index) = faces.iter().flatten().find(|&&i| i >= count) {
+            return Err(format!(
+                "vertex index {index} is out of range, there are {count} vertices"
+            ));
+        }
+
+        let colors: Vec<Color> = match reader.read_colors(0) {
+            Some(colors) => colors
+                .into_rgb_f32()
+                .map(|[r, g, b]| Color::new(r as f64, g as f64, b as f64))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let material = primitive.material();
+        let mat = match self.materials.get(&material.index()) {
+            Some(mat) => mat.clone(),
+            None => {
+                let albedo = self.base_color(&material);
+                let mat = convert_material(&material, albedo);
+                self.materials.insert(material.index(), mat.clone());
+                mat
+            }
+        };
+        let mesh = TriangleMesh::new(positions, normals, uvs, colors, faces, mat);
+        Ok(Some(Arc::new(MeshBvh::new(mesh))))
+    }
+
+    // The base color factor, multiplied by the base color texture if there is one
+    fn base_color(&mut self, material: &gltf::Material) -> Arc<dyn Texture> {
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let factor = Color::new(r as f64, g as f64, b as f64);
+        let Some(info) = pbr.base_color_texture() else {
+            return Arc::new(SolidColor::new(factor));
+        };
+
+        // Images are shared between materials unless a texture transform makes them differ
+        let texture = info.texture();
+        let transform = info.texture_transform().map(|t| uv_transform(&t));
+        let image = match self.textures.get(&texture.index()) {
+            Some(image) if transform.is_none() => image.clone(),
+            _ => {
+                let Some(data) = self.images.get(texture.source().index()) else {
+                    return Arc::new(SolidColor::new(factor));
+                };
+                let mut image = ImageTexture::new(
+                    data.width as usize,
+                    data.height as usize,
+                    decode_pixels(data),
+                );
+                let sampler = texture.sampler();
+                image.set_wrap(wrap_mode(sampler.wrap_s()), wrap_mode(sampler.wrap_t()));
+                if sampler.mag_filter() == Some(MagFilter::Nearest) {
+                    image.set_filter(Filter::Nearest);
+                }
+                if let Some(transform) = transform {
+                    image.set_transform(transform);
+                    return tinted(Arc::new(image), factor);
+                }
+                let image: Arc<dyn Texture> = Arc::new(image);
+                self.textures.insert(texture.index(), image.clone());
+                image
+            }
+        };
+        tinted(image, factor)
+    }
+}
+
+fn tinted(texture: Arc<dyn Texture>, tint: Color) -> Arc<dyn Texture> {
+    if tint == Color::new(1.0, 1.0, 1.0) {
+        texture
+    } else {
+        Arc::new(Tinted { texture, tint })
+    }
+}
+
+// A texture multiplied by a constant color, as glTF combines base color textures and factors
+struct Tinted {
+    texture: Arc<dyn Texture>,
+    tint: Color,
+}
+
+impl Texture for Tinted {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.tint * self.texture.value(u, v, p)
+    }
+}
+
+// Converts an image's texels to linear colors, decoding 8 and 16 bit channels from sRGB
+fn decode_pixels(data: &image::Data) -> Vec<Color> {
+    use image::Format::*;
+    let (channels, bytes) = match data.format {
+        R8 => (1, 1),
+        R8G8 => (2, 1),
+        R8G8B8 => (3, 1),
+        R8G8B8A8 => (4, 1),
+        R16 => (1, 2),
+        R16G16 => (2, 2),
+        R16G16B16 => (3, 2),
+        R16G16B16A16 => (4, 2),
+        R32G32B32FLOAT => (3, 4),
+        R32G32B32A32FLOAT => (4, 4),
+    };
+    data.pixels
+        .chunks_exact(channels * bytes)
+        .map(|texel| {
+            let channel = |c: usize| {
+                let c = c.min(channels - 1);
+                match bytes {
+                    1 => srgb_to_linear(texel[c] as f64 / 255.0),
+                    2 => srgb_to_linear(
+                        u16::from_le_bytes([texel[2 * c], texel[2 * c + 1]]) as f64 / 65535.0,
+                    ),
+                    // Float images are already linear
+                    _ => f32::from_le_bytes(texel[4 * c..4 * c + 4].try_into().unwrap()) as f64,
+                }
+            };
+            // Single channel images are grey, two channel images are grey with alpha
+            match channels {
+                1 | 2 => Color::new(channel(0), channel(0), channel(0)),
+                _ => Color::new(channel(0), channel(1), channel(2)),
+            }
+        })
+        .collect()
+}
+
+// KHR_texture_transform's offset, rotation and scale, which apply to glTF texture coordinates
+// with v pointing down, moved onto the flipped coordinates the meshes store
+fn uv_transform(transform: &TextureTransform) -> UvTransform {
+    let [sx, sy] = transform.scale().map(|s| s as f64);
+    let [ox, oy] = transform.offset().map(|o| o as f64);
+    let (sin, cos) = (transform.rotation() as f64).sin_cos();
+    let [[a, b, c], [d, e, f]] = [[cos * sx, sin * sy, ox], [-sin * sx, cos * sy, oy]];
+    UvTransform::new([[a, -b, b + c], [-d, e, 1.0 - e - f]])
+}
+
+fn wrap_mode(mode: WrappingMode) -> WrapMode {
+    match mode {
+        WrappingMode::Repeat => WrapMode::Repeat,
+        WrappingMode::MirroredRepeat => WrapMode::Mirror,
+        WrappingMode::ClampToEdge => WrapMode::Clamp,
+    }
+}
+
+fn convert_material(material: &gltf::Material, albedo: Arc<dyn Texture>) -> Arc<dyn Material> {
+    let pbr = material.pbr_metallic_roughness();
+
+    if let Some(emission) = emission(material) {
+        return Arc::new(DiffuseLight::new(emission));
+    }
+
+    let transmission = material
+        .transmission()
+        .map_or(0.0, |t| t.transmission_factor());
+    if transmission > 0.0 {
+        return Arc::new(Dielectric::new(material.ior().unwrap_or(1.5) as f64));
+    }
+
+    if pbr.metallic_factor() >= 0.5 {
+        let roughness = pbr.roughness_factor() as f64;
+        let fuzz = Color::new(roughness, roughness, roughness);
+        Arc::new(Metal::from_texture(albedo, Arc::new(SolidColor::new(fuzz))))
+    } else {
+        Arc::new(Lambertian::from_texture(albedo))
+    }
+}
+
+// The light a material emits, if any
+fn emission(material: &gltf::Material) -> Option<Color> {
+    let strength = material.emissive_strength().unwrap_or(1.0) as f64;
+    let [r, g, b] = material.emissive_factor();
+    let emission = Color::new(r as f64, g as f64, b as f64) * strength;
+    (emission.x().max(emission.y()).max(emission.z()) > 0.0).then_some(emission)
+}
+
+fn default_camera(lookfrom: Point3, lookat: Point3, vup: Vec3, vfov: f64) -> Camera {
+    Camera::new(1.0, 100, 10, 10, lookfrom, lookat, vup, vfov, 0.0, 10.0)
+}
+
+// Looks at the world's bounding box from +z, far enough back to see all of it
+fn framing_camera(world: &HittableList) -> Camera {
+    let bbox = world.bounding_box();
+    if bbox.is_empty() {
+        let lookat = Point3::new(0.0, 0.0, -1.0);
+        return default_camera(Point3::origin(), lookat, Vec3::new(0.0, 1.0, 0.0), 90.0);
+    }
+    let center = bbox.centroid();
+    let half_diagonal = Vec3::new(bbox.x.size(), bbox.y.size(), bbox.z.size()).length() / 2.0;
+    let vfov: f64 = 40.0;
+    let distance = half_diagonal / (vfov.to_radians() / 2.0).sin();
+    let lookfrom = center + Vec3::new(0.0, 0.0, distance);
+    default_camera(lookfrom, center, Vec3::new(0.0, 1.0, 0.0), vfov)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        gltf_scene::build_scene,
+        hittable::Hittable,
+        interval::Interval,
+        material::ScatterRecord,
+        ray::Ray,
+        sampler::Sampler,
+        vec3::{Point3, Vec3},
+    };
+
+    // A red triangle scaled by a child node and translated by its parent, plus a camera
+    const TRIANGLE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0, 2] }],
+        "nodes": [
+            { "translation": [0, 0, -5], "children": [1] },
+            { "mesh": 0, "scale": [2, 2, 2] },
+            { "camera": 0, "translation": [0, 0, 1] }
+        ],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }] }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0 } }],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.7, "znear": 0.1 } }],
+        "buffers": [{
+            "byteLength": 36,
+            "uri": "data:application/octet-stream;base64,AACAvwAAgL8AAAAAAACAPwAAgL8AAAAAAAAAAAAAgD8AAAAA"
+        }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+        "accessors": [{
+            "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+            "min": [-1, -1, 0], "max": [1, 1, 0]
+        }]
+    }"#;
+
+    #[test]
+    fn imports_transformed_meshes_and_materials() {
+        let mut sampler = Sampler::new(0);
+        let (document, buffers, images) = gltf::import_slice(TRIANGLE).unwrap();
+        let scene = build_scene(&document, &buffers, &images).unwrap();
+
+        // Only inside the triangle once the child's scale is applied
+        let r = Ray::new(Point3::new(1.5, -1.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = scene
+            .world
+            .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .unwrap();
+        assert_eq!(rec.t, 5.0);
+
+        let mat = rec.mat.clone().unwrap();
+        let Some(ScatterRecord::Sampled { attenuation, .. }) =
+            mat.scatter(&r, &rec, &mut Sampler::new(0))
+        else {
+            panic!("expected sampled scattering");
+        };
+        assert_eq!(attenuation, Color::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn reports_broken_primitives() {
+        // Two vertices make no triangle
+        let source = TRIANGLE.replace(r#""count": 3"#, r#""count": 2"#);
+        let (document, buffers, images) = gltf::import_slice(source).unwrap();
+        let message = match build_scene(&document, &buffers, &images) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("expected an error"),
+        };
+        assert_eq!(message, "mesh 0, primitive 0: no triangles");
+    }
+    #[test]
+    fn emissive_primitives_become_lights() {
+        let source = TRIANGLE.replace(
+            r#""metallicFactor": 0 }"#,
+            r#""metallicFactor": 0 }, "emissiveFactor": [1, 1, 1]"#,
+        );
+        let (document, buffers, images) = gltf::import_slice(source).unwrap();
+        let scene = build_scene(&document, &buffers, &images).unwrap();
+
+        // Sampled through the same transforms as the world's triangle
+        let (rec, pdf) = scene.lights.sample_surface(&mut Sampler::new(0)).unwrap();
+        assert!((rec.p.z() + 5.0).abs() < 1e-12);
+        assert!((pdf - 1.0 / 8.0).abs() < 1e-12);
+        assert_eq!(scene.lights.into_objects().len(), 1);
+    }
+}
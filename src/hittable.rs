@@ -1,18 +1,30 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
+    aabb::Aabb,
+    color::Color,
     interval::Interval,
     material::Material,
     ray::Ray,
+    sampler::Sampler,
     vec3::{Point3, Vec3},
 };
 
+#[derive(Clone)]
 pub(crate) struct HitRecord {
     pub(crate) p: Point3,
     pub(crate) normal: Vec3,
     pub(crate) t: f64,
+    // Surface coordinates of the hit point, each in [0, 1]
+    pub(crate) u: f64,
+    pub(crate) v: f64,
     pub(crate) front_face: bool,
-    pub(crate) mat: Option<Rc<dyn Material>>,
+    // Set for scattering points inside participating media, which lie on no surface; their
+    // normal is arbitrary
+    pub(crate) in_medium: bool,
+    // Interpolated vertex color for meshes that carry one, modulating the material's albedo
+    pub(crate) vertex_color: Option<Color>,
+    pub(crate) mat: Option<Arc<dyn Material>>,
 }
 
 impl HitRecord {
@@ -21,7 +33,11 @@ impl HitRecord {
             p: Point3::origin(),
             normal: Vec3::origin(),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
+            in_medium: false,
+            vertex_color: None,
             mat: None,
         }
     }
@@ -36,6 +52,33 @@ impl HitRecord {
     }
 }
 
-pub(crate) trait Hittable {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>;
+pub(crate) trait Hittable: Send + Sync {
+    // Media choose at random where a ray scatters inside them, drawing from the sampler of the
+    // path being traced so that separate media along a ray sample independently
+    fn hit(&self, r: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> Option<HitRecord>;
+
+    fn bounding_box(&self) -> Aabb;
+
+    // Lights are sampled by aiming rays at them. pdf_value is the density, per unit solid angle
+    // around origin, of random choosing direction; objects that cannot be aimed at keep the
+    // defaults and must not be used as lights.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn random(&self, _origin: &Point3, _sampler: &mut Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    // Light can also be traced out of lights, starting from points on their surfaces.
+    // sample_surface picks such a point, returning it as a hit record with the outward normal
+    // and the density per unit area of picking it; surface_pdf is that density for a point p
+    // with outward normal, or 0 if p is not on the object.
+    fn sample_surface(&self, _sampler: &mut Sampler) -> Option<(HitRecord, f64)> {
+        None
+    }
+
+    fn surface_pdf(&self, _p: &Point3, _normal: &Vec3) -> f64 {
+        0.0
+    }
 }
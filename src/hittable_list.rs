@@ -1,35 +1,49 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
+    aabb::Aabb,
     hittable::{HitRecord, Hittable},
     interval::Interval,
     ray::Ray,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
 };
 
 pub(crate) struct HittableList {
-    objects: Vec<Rc<dyn Hittable>>,
+    objects: Vec<Arc<dyn Hittable>>,
+    bbox: Aabb,
 }
 
 impl HittableList {
     pub(crate) fn empty() -> Self {
         Self {
             objects: Vec::new(),
+            bbox: Aabb::empty(),
         }
     }
 
-    pub(crate) fn add(&mut self, object: Rc<dyn Hittable>) {
+    pub(crate) fn add(&mut self, object: Arc<dyn Hittable>) {
+        self.bbox = Aabb::surrounding(&self.bbox, &object.bounding_box());
         self.objects.push(object);
     }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub(crate) fn into_objects(self) -> Vec<Arc<dyn Hittable>> {
+        self.objects
+    }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> Option<HitRecord> {
         let mut hit_anything = false;
         let mut cloest_so_far = ray_t.max;
         let mut rec = HitRecord::new();
 
-        for object in self.objects.clone() {
-            if let Some(r) = object.hit(r, &Interval::new(ray_t.min, cloest_so_far)) {
+        for object in &self.objects {
+            if let Some(r) = object.hit(r, &Interval::new(ray_t.min, cloest_so_far), sampler) {
                 hit_anything = true;
                 cloest_so_far = r.t;
                 rec = r;
@@ -41,4 +55,51 @@ impl Hittable for HittableList {
             None
         }
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // Aims at one of the objects, chosen uniformly
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|object| object.pdf_value(origin, direction))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let i = (sampler.random_f64() * self.objects.len() as f64) as usize;
+        self.objects[i.min(self.objects.len() - 1)].random(origin, sampler)
+    }
+
+    // Picks one of the objects uniformly, then a point on it
+    fn sample_surface(&self, sampler: &mut Sampler) -> Option<(HitRecord, f64)> {
+        if self.objects.is_empty() {
+            return None;
+        }
+        let i = (sampler.random_f64() * self.objects.len() as f64) as usize;
+        let (rec, pdf) = self.objects[i.min(self.objects.len() - 1)].sample_surface(sampler)?;
+        Some((rec, pdf / self.objects.len() as f64))
+    }
+
+    fn surface_pdf(&self, p: &Point3, normal: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|object| object.surface_pdf(p, normal))
+            .sum();
+        sum / self.objects.len() as f64
+    }
 }
@@ -0,0 +1,627 @@
+// Light transport algorithms. The camera generates rays through each pixel and an integrator
+// works out the color each one sees.
+
+use std::sync::Arc;
+
+use crate::{
+    camera::{Background, Camera},
+    color::Color,
+    framebuffer::Splats,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::ScatterRecord,
+    pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf},
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+// Everything an integrator needs besides the ray: the scene and the camera's settings for it
+pub(crate) struct RenderContext<'a> {
+    pub(crate) world: &'a dyn Hittable,
+    // Emitters that can be sampled directly, if any
+    pub(crate) lights: Option<&'a dyn Hittable>,
+    pub(crate) background: Background,
+    // The most bounces a path may take after the camera ray's first hit
+    pub(crate) max_depth: i64,
+    pub(crate) camera: &'a Camera,
+    // Light for pixels other than the one being sampled, scaled like it by the camera
+    pub(crate) splats: &'a Splats,
+}
+
+impl RenderContext<'_> {
+    pub(crate) fn hit(&self, r: &Ray, sampler: &mut Sampler) -> Option<HitRecord> {
+        self.world
+            .hit(r, &Interval::new(0.001, f64::INFINITY), sampler)
+    }
+}
+
+pub(crate) trait Integrator: Send + Sync {
+    // The color seen looking back along r. Hits without a material absorb the ray.
+    fn ray_color(&self, r: Ray, context: &RenderContext, sampler: &mut Sampler) -> Color;
+}
+
+// How bounces off diffuse and glossy materials find the scene's lights
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Sampling {
+    // Follow only the material's own distribution, finding lights by chance
+    Bsdf,
+    // Draw the one next direction from an even mixture of the material and the lights
+    Mixture,
+    // Multiple importance sampling: at each bounce, add a shadow ray towards the lights to the
+    // material's sample, weighting the light either reaches by the balance heuristic or by the
+    // power heuristic with exponent 2
+    Balance,
+    Power,
+}
+
+impl Sampling {
+    // The weight for a sample drawn with density pdf when the other strategy would have drawn it
+    // with density other_pdf
+    fn weight(&self, pdf: f64, other_pdf: f64) -> f64 {
+        match self {
+            Sampling::Power => {
+                let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+                a / (a + b)
+            }
+            _ => pdf / (pdf + other_pdf),
+        }
+    }
+}
+
+// The emission r finds at rec. scatter_pdf is the density with which the material at r's origin
+// chose r, when light sampling there could also have found the same light; the emission is then
+// weighted against that light sampling.
+fn weighted_emission(
+    r: &Ray,
+    rec: &HitRecord,
+    lights: Option<&dyn Hittable>,
+    scatter_pdf: Option<f64>,
+    sampling: Sampling,
+) -> Color {
+    let Some(m) = &rec.mat else {
+        return Color::origin();
+    };
+    let emission = m.emitted(r, rec);
+    if let (Some(pdf), Some(lights)) = (scatter_pdf, lights) {
+        let light_pdf = lights.pdf_value(&r.origin(), &r.direction());
+        if light_pdf > 0.0 {
+            return sampling.weight(pdf, light_pdf) * emission;
+        }
+    }
+    emission
+}
+
+// The light reaching rec along one shadow ray aimed at the lights, counting only the emission
+// it reaches directly, weighted against the material's own sampling with density pdf. It is
+// scaled by the material's scattering_pdf but not by its attenuation.
+fn sample_lights(
+    context: &RenderContext,
+    lights: &dyn Hittable,
+    r: &Ray,
+    rec: &HitRecord,
+    pdf: &dyn Pdf,
+    sampling: Sampling,
+    sampler: &mut Sampler,
+) -> Color {
+    let Some(m) = &rec.mat else {
+        return Color::origin();
+    };
+    let direction = lights.random(&rec.p, sampler);
+    let light_pdf = lights.pdf_value(&rec.p, &direction);
+    let shadow = Ray::with_time(rec.p, direction, r.time());
+    let scattering_pdf = m.scattering_pdf(r, rec, &shadow);
+    if light_pdf <= 0.0 || scattering_pdf <= 0.0 {
+        return Color::origin();
+    }
+    let Some(light_rec) = context.hit(&shadow, sampler) else {
+        return Color::origin();
+    };
+    let Some(light_mat) = &light_rec.mat else {
+        return Color::origin();
+    };
+    let weight = sampling.weight(light_pdf, pdf.value(&direction));
+    (weight * scattering_pdf / light_pdf) * light_mat.emitted(&shadow, &light_rec)
+}
+
+// Unbiased Monte Carlo path tracing: follows each path one bounce at a time, carrying
+// throughput, the fraction of light found further along the path that reaches the camera
+pub(crate) struct PathTracer {
+    sampling: Sampling,
+    // None never ends paths early; they run until they escape or reach max_depth
+    roulette_depth: Option<i64>,
+}
+
+impl PathTracer {
+    pub(crate) fn new() -> Self {
+        Self {
+            sampling: Sampling::Power,
+            roulette_depth: Some(3),
+        }
+    }
+
+    pub(crate) fn set_sampling(&mut self, sampling: Sampling) {
+        self.sampling = sampling;
+    }
+
+    pub(crate) fn set_roulette_depth(&mut self, roulette_depth: Option<i64>) {
+        self.roulette_depth = roulette_depth;
+    }
+
+    // Russian roulette: past roulette_depth bounces, a path ends with a probability that grows
+    // as its throughput dims, and survivors are brightened to make up for those that ended
+    fn survives_roulette(&self, depth: i64, throughput: &mut Color, sampler: &mut Sampler) -> bool {
+        match self.roulette_depth {
+            Some(roulette_depth) if depth >= roulette_depth => {
+                let survival = throughput
+                    .x()
+                    .max(throughput.y())
+                    .max(throughput.z())
+                    .min(1.0);
+                if sampler.random_f64() >= survival {
+                    return false;
+                }
+                *throughput /= survival;
+                true
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Integrator for PathTracer {
+    fn ray_color(&self, mut ray: Ray, context: &RenderContext, sampler: &mut Sampler) -> Color {
+        let mut color = Color::origin();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut scatter_pdf: Option<f64> = None;
+
+        for depth in 0..=context.max_depth {
+            let Some(rec) = context.hit(&ray, sampler) else {
+                color += throughput * context.background.color(&ray);
+                break;
+            };
+            let Some(m) = &rec.mat else {
+                break;
+            };
+            color += throughput
+                * weighted_emission(&ray, &rec, context.lights, scatter_pdf, self.sampling);
+
+            let (attenuation, pdf) = match m.scatter(&ray, &rec, sampler) {
+                None => break,
+                Some(ScatterRecord::Specular {
+                    attenuation,
+                    ray: scattered,
+                }) => {
+                    throughput = throughput * attenuation;
+                    ray = scattered;
+                    scatter_pdf = None;
+                    if !self.survives_roulette(depth, &mut throughput, sampler) {
+                        break;
+                    }
+                    continue;
+                }
+                Some(ScatterRecord::Sampled { attenuation, pdf }) => (attenuation, pdf),
+            };
+
+            // With multiple importance sampling, add a shadow ray towards the lights. Not at the
+            // last bounce, where the material's sample it is weighted against is never traced.
+            let mis_lights = match self.sampling {
+                Sampling::Balance | Sampling::Power if depth < context.max_depth => context.lights,
+                _ => None,
+            };
+            if let Some(lights) = mis_lights {
+                let direct = sample_lights(
+                    context,
+                    lights,
+                    &ray,
+                    &rec,
+                    pdf.as_ref(),
+                    self.sampling,
+                    sampler,
+                );
+                color += throughput * attenuation * direct;
+            }
+
+            let (direction, pdf_value) = match (self.sampling, context.lights) {
+                // Draw the next direction half the time towards the lights and half the time
+                // from the material
+                (Sampling::Mixture, Some(lights)) => {
+                    let light_pdf = HittablePdf::new(lights, rec.p);
+                    let mixture = MixturePdf::new(&light_pdf, pdf.as_ref());
+                    let direction = mixture.generate(sampler);
+                    (direction, mixture.value(&direction))
+                }
+                _ => {
+                    let direction = pdf.generate(sampler);
+                    (direction, pdf.value(&direction))
+                }
+            };
+            if pdf_value <= 0.0 {
+                break;
+            }
+            let scattered = Ray::with_time(rec.p, direction, ray.time());
+            let scattering_pdf = m.scattering_pdf(&ray, &rec, &scattered);
+            throughput = (scattering_pdf / pdf_value) * throughput * attenuation;
+            ray = scattered;
+            scatter_pdf = mis_lights.map(|_| pdf_value);
+            if !self.survives_roulette(depth, &mut throughput, sampler) {
+                break;
+            }
+        }
+        color
+    }
+}
+
+// Only light that reaches a surface straight from an emitter or the background, following
+// mirrors and glass up to max_depth bounces. Combines light and material sampling with the
+// power heuristic.
+pub(crate) struct DirectLighting;
+
+impl DirectLighting {
+    // The light arriving directly at rec, scaled by the material's scattering_pdf
+    fn direct(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        pdf: &dyn Pdf,
+        context: &RenderContext,
+        sampler: &mut Sampler,
+    ) -> Color {
+        let Some(m) = &rec.mat else {
+            return Color::origin();
+        };
+        let mut color = Color::origin();
+        if let Some(lights) = context.lights {
+            color += sample_lights(context, lights, r, rec, pdf, Sampling::Power, sampler);
+        }
+
+        let direction = pdf.generate(sampler);
+        let pdf_value = pdf.value(&direction);
+        if pdf_value <= 0.0 {
+            return color;
+        }
+        let scattered = Ray::with_time(rec.p, direction, r.time());
+        let arriving = match context.hit(&scattered, sampler) {
+            Some(next) => weighted_emission(
+                &scattered,
+                &next,
+                context.lights,
+                Some(pdf_value),
+                Sampling::Power,
+            ),
+            None => context.background.color(&scattered),
+        };
+        color + (m.scattering_pdf(r, rec, &scattered) / pdf_value) * arriving
+    }
+}
+
+impl Integrator for DirectLighting {
+    fn ray_color(&self, mut ray: Ray, context: &RenderContext, sampler: &mut Sampler) -> Color {
+        let mut color = Color::origin();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        for _ in 0..=context.max_depth {
+            let Some(rec) = context.hit(&ray, sampler) else {
+                color += throughput * context.background.color(&ray);
+                break;
+            };
+            let Some(m) = &rec.mat else {
+                break;
+            };
+            color += throughput * m.emitted(&ray, &rec);
+            match m.scatter(&ray, &rec, sampler) {
+                None => break,
+                Some(ScatterRecord::Specular {
+                    attenuation,
+                    ray: scattered,
+                }) => {
+                    throughput = throughput * attenuation;
+                    ray = scattered;
+                }
+                Some(ScatterRecord::Sampled { attenuation, pdf }) => {
+                    let direct = self.direct(&ray, &rec, pdf.as_ref(), context, sampler);
+                    color += throughput * attenuation * direct;
+                    break;
+                }
+            }
+        }
+        color
+    }
+}
+
+// White where a random direction from the first hit, drawn with a cosine weighting around the
+// normal, escapes without meeting anything within distance, and black otherwise, so creases and
+// contact points darken. Rays that miss everything are black.
+pub(crate) struct AmbientOcclusion {
+    distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub(crate) fn new(distance: f64) -> Self {
+        Self { distance }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn ray_color(&self, r: Ray, context: &RenderContext, sampler: &mut Sampler) -> Color {
+        let Some(rec) = context.hit(&r, sampler) else {
+            return Color::origin();
+        };
+        let direction = CosinePdf::new(rec.normal).generate(sampler);
+        let probe = Ray::with_time(rec.p, direction, r.time());
+        let reach = Interval::new(0.001, self.distance / direction.length());
+        match context.world.hit(&probe, &reach, sampler) {
+            Some(_) => Color::origin(),
+            None => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// What DebugView shows about the first surface each camera ray hits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DebugOutput {
+    // The normal facing the camera, mapped from [-1, 1] to [0, 1] per axis
+    Normals,
+    // The distance from the camera as a grey level in scene units, unclamped; write an HDR or
+    // EXR file to keep values above 1
+    Depth,
+    // The texture coordinates u and v as red and green
+    Uv,
+    // Each material in its own arbitrary color
+    Material,
+}
+
+// Shows a property of the first hit instead of its lighting. Rays that miss everything are black.
+pub(crate) struct DebugView {
+    output: DebugOutput,
+}
+
+impl DebugView {
+    pub(crate) fn new(output: DebugOutput) -> Self {
+        Self { output }
+    }
+}
+
+impl Integrator for DebugView {
+    fn ray_color(&self, r: Ray, context: &RenderContext, sampler: &mut Sampler) -> Color {
+        let Some(rec) = context.hit(&r, sampler) else {
+            return Color::origin();
+        };
+        match self.output {
+            DebugOutput::Normals => 0.5 * (rec.normal + Vec3::new(1.0, 1.0, 1.0)),
+            DebugOutput::Depth => {
+                let distance = rec.t * r.direction().length();
+                Color::new(distance, distance, distance)
+            }
+            DebugOutput::Uv => Color::new(rec.u, rec.v, 0.0),
+            DebugOutput::Material => match &rec.mat {
+                // Materials are shared, so their addresses identify them
+                Some(m) => Color::random(&mut Sampler::new(
+                    Arc::as_ptr(m) as *const () as usize as u64
+                )),
+                None => Color::origin(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        camera::{Background, Camera},
+        color::Color,
+        framebuffer::Splats,
+        hittable::Hittable,
+        hittable_list::HittableList,
+        integrator::{
+            AmbientOcclusion, DebugOutput, DebugView, DirectLighting, Integrator, PathTracer,
+            RenderContext, Sampling,
+        },
+        material::{DiffuseLight, Lambertian, Metal},
+        quad::Quad,
+        ray::Ray,
+        sampler::Sampler,
+        sphere::Sphere,
+        vec3::{Point3, Vec3},
+    };
+
+    // A diffuse floor and a fuzzy metal one under a small light, with the light as the only
+    // object in lights
+    fn lit_floor() -> (HittableList, HittableList) {
+        let mut world = HittableList::empty();
+        world.add(Arc::new(Quad::new(
+            Point3::new(-2.0, 0.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        )));
+        world.add(Arc::new(Quad::new(
+            Point3::new(0.0, 0.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.3)),
+        )));
+        let light: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Point3::new(-0.25, 1.0, -0.25),
+            Vec3::new(0.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.5),
+            Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 10.0))),
+        ));
+        world.add(light.clone());
+        let mut lights = HittableList::empty();
+        lights.add(light);
+        (world, lights)
+    }
+
+    // The mean brightness of a small render looking down at the origin from lookfrom
+    pub(crate) fn brightness(
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        integrator: &dyn Integrator,
+        lookfrom: Point3,
+        (samples_per_pixel, max_depth): (i64, i64),
+    ) -> f64 {
+        let mut camera = Camera::new(
+            1.0,
+            8,
+            samples_per_pixel,
+            max_depth,
+            lookfrom,
+            Point3::origin(),
+            Vec3::new(0.0, 0.0, -1.0),
+            60.0,
+            0.0,
+            10.0,
+        );
+        camera.set_background(Background::None);
+        camera_brightness(&mut camera, world, lights, integrator)
+    }
+
+    // The mean brightness of what camera renders
+    pub(crate) fn camera_brightness(
+        camera: &mut Camera,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        integrator: &dyn Integrator,
+    ) -> f64 {
+        let image = camera.render(world, Some(lights), integrator);
+        let sum = image
+            .pixels()
+            .iter()
+            .fold(Color::origin(), |sum, &pixel| sum + pixel);
+        (sum.x() + sum.y() + sum.z()) / (3 * image.pixels().len()) as f64
+    }
+
+    pub(crate) fn assert_close(b: f64, reference: f64, what: &str) {
+        assert!(
+            (b - reference).abs() < 0.03 * reference,
+            "{what}: {b} against {reference}"
+        );
+    }
+
+    #[test]
+    fn sampling_strategies_agree_on_brightness() {
+        let (world, lights) = lit_floor();
+        let brightness_with = |sampling: Sampling| {
+            let mut path_tracer = PathTracer::new();
+            path_tracer.set_sampling(sampling);
+            brightness(
+                &world,
+                &lights,
+                &path_tracer,
+                Point3::new(0.0, 3.0, 3.0),
+                (2000, 4),
+            )
+        };
+        let reference = brightness_with(Sampling::Bsdf);
+        for sampling in [Sampling::Mixture, Sampling::Balance, Sampling::Power] {
+            assert_close(
+                brightness_with(sampling),
+                reference,
+                &format!("{sampling:?}"),
+            );
+        }
+    }
+
+    #[test]
+    fn direct_lighting_is_a_single_bounce() {
+        let (world, lights) = lit_floor();
+        let mut path_tracer = PathTracer::new();
+        path_tracer.set_roulette_depth(None);
+        let lookfrom = Point3::new(0.0, 3.0, 3.0);
+        let reference = brightness(&world, &lights, &path_tracer, lookfrom, (2000, 1));
+        let direct = brightness(&world, &lights, &DirectLighting, lookfrom, (2000, 1));
+        assert_close(direct, reference, "direct");
+    }
+
+    #[test]
+    fn roulette_keeps_deep_lighting() {
+        // Inside a closed, bright room lit by a small lamp, light bounces many times
+        let mut world = HittableList::empty();
+        world.add(Arc::new(Sphere::new(
+            Point3::origin(),
+            5.0,
+            Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        )));
+        let lamp: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::new(0.0, 4.0, 0.0),
+            0.5,
+            Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
+        ));
+        world.add(lamp.clone());
+
+        let brightness_with = |roulette_depth: Option<i64>| {
+            let mut path_tracer = PathTracer::new();
+            path_tracer.set_roulette_depth(roulette_depth);
+            brightness(
+                &world,
+                lamp.as_ref(),
+                &path_tracer,
+                Point3::new(0.0, 0.1, 0.0),
+                (250, 40),
+            )
+        };
+        assert_close(brightness_with(Some(0)), brightness_with(None), "roulette");
+    }
+
+    #[test]
+    fn shows_first_hits() {
+        let (world, lights) = lit_floor();
+        let camera = Camera::new(
+            1.0,
+            1,
+            1,
+            10,
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::origin(),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            0.0,
+            1.0,
+        );
+        let context = RenderContext {
+            world: &world,
+            lights: Some(&lights),
+            background: Background::Gradient,
+            max_depth: 10,
+            camera: &camera,
+            splats: &Splats::new(1, 1),
+        };
+        let mut sampler = Sampler::new(0);
+        let mut show = |integrator: &dyn Integrator, origin: Point3| {
+            let r = Ray::new(origin, Vec3::new(0.0, -2.0, 0.0));
+            integrator.ray_color(r, &context, &mut sampler)
+        };
+        let floor = Point3::new(-1.5, 3.0, 0.5);
+        assert_eq!(
+            show(&DebugView::new(DebugOutput::Normals), floor),
+            Color::new(0.5, 1.0, 0.5)
+        );
+        assert_eq!(
+            show(&DebugView::new(DebugOutput::Depth), floor),
+            Color::new(3.0, 3.0, 3.0)
+        );
+        assert_eq!(
+            show(&DebugView::new(DebugOutput::Uv), floor),
+            Color::new(0.25, 0.625, 0.0)
+        );
+        let material = DebugView::new(DebugOutput::Material);
+        assert_eq!(
+            show(&material, floor),
+            show(&material, floor + Vec3::new(0.5, 0.0, 0.0))
+        );
+        assert_ne!(
+            show(&material, floor),
+            show(&material, Point3::new(1.0, 3.0, 0.0))
+        );
+        assert_eq!(show(&material, Point3::new(5.0, 3.0, 0.0)), Color::origin());
+
+        // Under the light, short probes never reach it but long ones sometimes do
+        let under_light = Point3::new(0.0, 0.5, 0.0);
+        let short = AmbientOcclusion::new(0.5);
+        assert!((0..20).all(|_| show(&short, under_light) == Color::new(1.0, 1.0, 1.0)));
+        let long = AmbientOcclusion::new(f64::INFINITY);
+        assert!((0..20).any(|_| show(&long, under_light) == Color::origin()));
+    }
+}
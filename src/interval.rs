@@ -1,6 +1,6 @@
 use core::f64;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub(crate) struct Interval {
     pub(crate) min: f64,
     pub(crate) max: f64,
@@ -11,6 +11,19 @@ impl Interval {
         Self { min, max }
     }
 
+    pub(crate) fn empty() -> Self {
+        Self::new(f64::INFINITY, f64::NEG_INFINITY)
+    }
+
+    // The tightest interval enclosing both a and b
+    pub(crate) fn enclosing(a: &Interval, b: &Interval) -> Self {
+        Self::new(a.min.min(b.min), a.max.max(b.max))
+    }
+
+    pub(crate) fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
     pub(crate) fn surrounds(&self, x: f64) -> bool {
         self.min < x && x < self.max
     }
@@ -24,4 +37,9 @@ impl Interval {
             x
         }
     }
+
+    pub(crate) fn expand(&self, delta: f64) -> Self {
+        let padding = delta / 2.0;
+        Self::new(self.min - padding, self.max + padding)
+    }
 }
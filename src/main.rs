@@ -1,90 +1,129 @@
+mod aabb;
+mod bdpt;
+mod builtin_scenes;
+mod bvh;
 mod camera;
+mod cli;
 mod color;
+mod constant_medium;
+mod framebuffer;
+mod gltf_scene;
 mod hittable;
 mod hittable_list;
+mod integrator;
 mod interval;
 mod material;
+mod matrix;
+mod mesh_bvh;
+mod noise;
+mod obj;
+mod onb;
+mod output;
+mod pdf;
+mod ply;
+mod quad;
 mod ray;
+mod sampler;
+mod scene;
 mod sphere;
+mod texture;
+mod transform;
+mod triangle;
 mod utility;
 mod vec3;
+mod volume;
 
-use camera::Camera;
-use color::Color;
-use hittable_list::HittableList;
-use material::{Dielectric, Lambertian, Material, Metal};
-use sphere::Sphere;
-use utility::{random_f64, random_f64_range};
-use vec3::{Point3, Vec3};
+use bdpt::Bidirectional;
+use builtin_scenes::{builtin_scene, BUILTIN_SCENES};
+use bvh::BvhNode;
+use cli::{Command, IntegratorChoice, Options, SceneSource};
+use hittable::Hittable;
+use integrator::{AmbientOcclusion, DebugView, DirectLighting, Integrator, PathTracer};
+use sampler::Sampler;
+use scene::Scene;
 
-use std::rc::Rc;
+use std::{env, process, sync::Arc};
 
 fn main() {
-    // World
-    let mut world = HittableList::empty();
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(Command::Render(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\nFor more information, try '--help'.");
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(options) {
+        eprintln!("error: {e}");
+        process::exit(1);
+    }
+}
 
-    let material_ground = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(Rc::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        material_ground,
-    )));
+fn run(options: Options) -> Result<(), String> {
+    let Scene {
+        world,
+        mut camera,
+        lights,
+    } = match &options.scene {
+        SceneSource::File(path) => match path.extension().and_then(|e| e.to_str()) {
+            Some("gltf" | "glb") => gltf_scene::load_gltf(path)
+                .map_err(|e| format!("failed to load {}: {e}", path.display()))?,
+            _ => scene::load_scene(path, &mut Sampler::new(options.seed))
+                .map_err(|e| format!("failed to load {}: {e}", path.display()))?,
+        },
+        SceneSource::Builtin(name) => builtin_scene(name, &mut Sampler::new(options.seed))
+            .ok_or_else(|| {
+                format!(
+                    "unknown built-in scene '{name}', expected one of {}",
+                    BUILTIN_SCENES.join(", ")
+                )
+            })?,
+    };
 
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_f64();
-            let center = Point3::new(
-                a as f64 + 0.9 * random_f64(),
-                0.2,
-                b as f64 + 0.9 * random_f64(),
-            );
+    if let Some(image_width) = options.image_width {
+        camera.set_image_width(image_width);
+    }
+    if let Some(aspect_ratio) = options.aspect_ratio {
+        camera.set_aspect_ratio(aspect_ratio);
+    }
+    if let Some(samples_per_pixel) = options.samples_per_pixel {
+        camera.set_samples_per_pixel(samples_per_pixel);
+    }
+    if let Some(max_depth) = options.max_depth {
+        camera.set_max_depth(max_depth);
+    }
+    if let Some(threads) = options.threads {
+        camera.set_threads(threads);
+    }
+    camera.set_seed(options.seed);
 
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let sphere_material: Rc<dyn Material> = if choose_mat < 0.8 {
-                    let albedo = Color::random() * Color::random();
-                    Rc::new(Lambertian::new(albedo))
-                } else if choose_mat < 0.95 {
-                    let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz = random_f64_range(0.0, 0.5);
-                    Rc::new(Metal::new(albedo, fuzz))
-                } else {
-                    Rc::new(Dielectric::new(1.5))
-                };
-                world.add(Rc::new(Sphere::new(center, 0.2, sphere_material)));
-            }
+    let integrator: Box<dyn Integrator> = match options.integrator {
+        IntegratorChoice::Path => {
+            let mut path_tracer = PathTracer::new();
+            path_tracer.set_sampling(options.sampling);
+            path_tracer.set_roulette_depth(options.roulette_depth);
+            Box::new(path_tracer)
         }
-    }
+        IntegratorChoice::Bidirectional => Box::new(Bidirectional),
+        IntegratorChoice::Direct => Box::new(DirectLighting),
+        IntegratorChoice::AmbientOcclusion => Box::new(AmbientOcclusion::new(options.ao_distance)),
+        IntegratorChoice::Debug(output) => Box::new(DebugView::new(output)),
+    };
 
-    let material1 = Rc::new(Dielectric::new(1.5));
-    world.add(Rc::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
-    let material2 = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Rc::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
-    let material3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Rc::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
+    let world: Arc<dyn Hittable> = match options.split {
+        Some(split) => Arc::new(BvhNode::new(world, split)),
+        None => Arc::new(world),
+    };
+    let lights: Option<&dyn Hittable> = if lights.is_empty() {
+        None
+    } else {
+        Some(&lights)
+    };
+    let image = camera.render(world.as_ref(), lights, integrator.as_ref());
 
-    let mut cam = Camera::new(
-        16.0 / 9.0,
-        1200,
-        500,
-        50,
-        Point3::new(13.0, 2.0, 3.0),
-        Point3::origin(),
-        Vec3::new(0.0, 1.0, 0.0),
-        20.0,
-        0.6,
-        10.0,
-    );
-    cam.render(world);
+    output::save(&image, &options.output, options.format)
+        .map_err(|e| format!("failed to write {}: {e}", options.output.display()))
 }
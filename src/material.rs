@@ -1,53 +1,152 @@
-use crate::{color::Color, hittable::HitRecord, ray::Ray, utility::random_f64, vec3::Vec3};
+use std::{f64::consts::PI, sync::Arc};
 
-pub(crate) trait Material {
-    fn scatter(&self, _r_in: &Ray, _recc: &HitRecord) -> Option<(Ray, Color)> {
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    pdf::{CosinePdf, FuzzyReflectionPdf, HenyeyGreensteinPdf, Pdf, SpherePdf},
+    ray::Ray,
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
+};
+
+// How a material scatters a ray arriving at a hit
+pub(crate) enum ScatterRecord {
+    // Along a single ray chosen by the material, as for mirrors and glass, where sampling other
+    // directions such as towards lights would be pointless
+    Specular {
+        attenuation: Color,
+        ray: Ray,
+    },
+    // In a direction the renderer draws from pdf, or from the lights, weighted by the material's
+    // scattering_pdf. Diffuse and glossy surfaces and media scatter this way.
+    Sampled {
+        attenuation: Color,
+        pdf: Box<dyn Pdf>,
+    },
+}
+
+pub(crate) trait Material: Send + Sync {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _recc: &HitRecord,
+        _sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
         None
     }
+
+    // The density, per unit solid angle, of the material scattering r_in into scattered
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::origin()
+    }
+
+    // Paths traced out of lights rather than back from the camera carry importance, which a
+    // specular scatter of r_in into scattered changes by this factor on top of the attenuation
+    fn importance_scale(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0
+    }
+}
+
+// Looks up the albedo at the hit, tinted by its vertex color if any
+fn albedo_at(albedo: &dyn Texture, rec: &HitRecord) -> Color {
+    let albedo = albedo.value(rec.u, rec.v, &rec.p);
+    rec.vertex_color.map_or(albedo, |c| c * albedo)
 }
 
 pub(crate) struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub(crate) fn new(albedo: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub(crate) fn from_texture(albedo: Arc<dyn Texture>) -> Self {
         Self { albedo }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let scattered_direction = rec.normal + Vec3::random_unit_vector();
-        let scattered = Ray::new(rec.p, scattered_direction);
-        Some((scattered, self.albedo))
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        _sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled {
+            attenuation: albedo_at(self.albedo.as_ref(), rec),
+            pdf: Box::new(CosinePdf::new(rec.normal)),
+        })
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = rec.normal.dot(&scattered.direction().unit_vector());
+        (cos_theta / PI).max(0.0)
     }
 }
 
 pub(crate) struct Metal {
-    albedo: Color,
-    fuzz: f64,
+    albedo: Arc<dyn Texture>,
+    // Read as the mean of the texture's channels, so a grey texture gives its brightness
+    fuzz: Arc<dyn Texture>,
 }
 
 impl Metal {
     pub(crate) fn new(albedo: Color, fuzz: f64) -> Self {
-        Self {
-            albedo,
-            fuzz: if fuzz >= 1.0 { 1.0 } else { fuzz },
-        }
+        Self::from_texture(
+            Arc::new(SolidColor::new(albedo)),
+            Arc::new(SolidColor::new(Color::new(fuzz, fuzz, fuzz))),
+        )
+    }
+
+    pub(crate) fn from_texture(albedo: Arc<dyn Texture>, fuzz: Arc<dyn Texture>) -> Self {
+        Self { albedo, fuzz }
+    }
+}
+
+impl Metal {
+    fn fuzz_at(&self, rec: &HitRecord) -> f64 {
+        let fuzz = self.fuzz.value(rec.u, rec.v, &rec.p);
+        ((fuzz.x() + fuzz.y() + fuzz.z()) / 3.0).clamp(0.0, 1.0)
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let mut reflected = r_in.direction().reflection(&rec.normal);
-        reflected = reflected.unit_vector() + (self.fuzz * Vec3::random_unit_vector());
-        let scattered = Ray::new(rec.p, reflected);
-        if scattered.direction().dot(&rec.normal) > 0.0 {
-            Some((scattered, self.albedo))
-        } else {
-            None
+    // A polished metal is a mirror; a fuzzy one scatters around the mirror direction and can be
+    // sampled towards lights like a diffuse surface
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        let reflected = r_in.direction().reflection(&rec.normal).unit_vector();
+        let attenuation = albedo_at(self.albedo.as_ref(), rec);
+        let fuzz = self.fuzz_at(rec);
+        if fuzz <= 0.0 {
+            return Some(ScatterRecord::Specular {
+                attenuation,
+                ray: Ray::with_time(rec.p, reflected, r_in.time()),
+            });
         }
+        Some(ScatterRecord::Sampled {
+            attenuation,
+            pdf: Box::new(FuzzyReflectionPdf::new(reflected, fuzz)),
+        })
+    }
+
+    // Directions that would leave below the surface are absorbed
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        if scattered.direction().dot(&rec.normal) <= 0.0 {
+            return 0.0;
+        }
+        let reflected = r_in.direction().reflection(&rec.normal).unit_vector();
+        FuzzyReflectionPdf::new(reflected, self.fuzz_at(rec)).value(&scattered.direction())
     }
 }
 
@@ -71,7 +170,7 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let ri = if rec.front_face {
             1.0 / self.refraction_index
@@ -79,16 +178,161 @@ impl Material for Dielectric {
             self.refraction_index
         };
 
-        let unit_direction = -r_in.direction().unit_vector();
-        let cos_theta = f64::min(unit_direction.dot(&rec.normal), 1.0);
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = f64::min(-unit_direction.dot(&rec.normal), 1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract = ri * sin_theta > 1.0;
-        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > random_f64() {
+        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > sampler.random_f64()
+        {
             unit_direction.reflection(&rec.normal)
         } else {
             unit_direction.refract(&rec.normal, ri)
         };
-        let scattered = Ray::new(rec.p, direction);
-        Some((scattered, attenuation))
+        let scattered = Ray::with_time(rec.p, direction, r_in.time());
+        Some(ScatterRecord::Specular {
+            attenuation,
+            ray: scattered,
+        })
+    }
+
+    // Radiance traced back from the camera passes through unscaled, so light traced the other
+    // way is scaled by the squared ratio of refractive indices to match
+    fn importance_scale(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        if scattered.direction().dot(&rec.normal) >= 0.0 {
+            return 1.0;
+        }
+        let ratio = if rec.front_face {
+            1.0 / self.refraction_index
+        } else {
+            self.refraction_index
+        };
+        ratio * ratio
+    }
+}
+
+// Light source that emits the same radiance in every direction and never scatters
+pub(crate) struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub(crate) fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        self.emit
+    }
+}
+
+// Phase function for participating media, scattering equally in every direction
+pub(crate) struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub(crate) fn new(albedo: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub(crate) fn from_texture(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        _sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled {
+            attenuation: albedo_at(self.albedo.as_ref(), rec),
+            pdf: Box::new(SpherePdf),
+        })
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+// Phase function for media that scatter mostly forwards (g > 0), such as clouds, or mostly
+// backwards (g < 0). g is the mean cosine between the incoming and scattered directions; 0 is
+// isotropic.
+pub(crate) struct HenyeyGreenstein {
+    albedo: Arc<dyn Texture>,
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub(crate) fn from_texture(albedo: Arc<dyn Texture>, g: f64) -> Self {
+        // At |g| = 1 the distribution collapses to a single direction
+        Self {
+            albedo,
+            g: g.clamp(-0.999, 0.999),
+        }
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled {
+            attenuation: albedo_at(self.albedo.as_ref(), rec),
+            pdf: Box::new(HenyeyGreensteinPdf::new(r_in.direction(), self.g)),
+        })
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> f64 {
+        HenyeyGreensteinPdf::new(r_in.direction(), self.g).value(&scattered.direction())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        color::Color,
+        hittable::HitRecord,
+        material::{HenyeyGreenstein, Material, ScatterRecord},
+        ray::Ray,
+        sampler::Sampler,
+        texture::SolidColor,
+        vec3::{Point3, Vec3},
+    };
+
+    #[test]
+    fn henyey_greenstein_has_mean_cosine_g() {
+        let white = Arc::new(SolidColor::new(Color::new(1.0, 1.0, 1.0)));
+        let r_in = Ray::new(Point3::origin(), Vec3::new(0.0, 0.0, -3.0));
+        let rec = HitRecord::new();
+        let mut sampler = Sampler::new(0);
+        for g in [-0.5, 0.0, 0.8] {
+            let phase = HenyeyGreenstein::from_texture(white.clone(), g);
+            let Some(ScatterRecord::Sampled { pdf, .. }) = phase.scatter(&r_in, &rec, &mut sampler)
+            else {
+                panic!("expected sampled scattering");
+            };
+            let samples = 20_000;
+            let mean_cos = (0..samples)
+                .map(|_| -pdf.generate(&mut sampler).unit_vector().z())
+                .sum::<f64>()
+                / samples as f64;
+            assert!((mean_cos - g).abs() < 0.02, "g = {g}: {mean_cos}");
+
+            // The density the renderer weights by matches the one directions are drawn from
+            let scattered = Ray::new(Point3::origin(), Vec3::new(0.3, -0.2, 0.5));
+            let density = phase.scattering_pdf(&r_in, &rec, &scattered);
+            assert!((density - pdf.value(&scattered.direction())).abs() < 1e-12);
+        }
     }
 }
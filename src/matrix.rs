@@ -0,0 +1,197 @@
+use std::ops::Mul;
+
+use crate::{
+    utility::degrees_to_radians,
+    vec3::{Point3, Vec3},
+};
+
+// A 4x4 matrix for affine transforms of points and vectors, stored row-major
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Matrix {
+    m: [[f64; 4]; 4],
+}
+
+impl Matrix {
+    pub(crate) fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub(crate) fn from_columns(columns: [[f64; 4]; 4]) -> Self {
+        Self::new(columns).transpose()
+    }
+
+    pub(crate) fn identity() -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub(crate) fn translation(offset: Vec3) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub(crate) fn scaling(factors: Vec3) -> Self {
+        Self::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counter-clockwise rotation about the axis when looking back along it
+    pub(crate) fn rotation(axis: Vec3, degrees: f64) -> Self {
+        let a = axis.unit_vector();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let theta = degrees_to_radians(degrees);
+        let (sin, cos) = theta.sin_cos();
+        let t = 1.0 - cos;
+        Self::new([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub(crate) fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Self::new(m)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting; None for a singular matrix
+    pub(crate) fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                let factor = a[row][col];
+                if row == col || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+        Some(Self::new(inv))
+    }
+
+    // The determinant of the linear part, the factor by which the transform scales volumes
+    pub(crate) fn linear_determinant(&self) -> f64 {
+        let column = |j: usize| Vec3::new(self.m[0][j], self.m[1][j], self.m[2][j]);
+        column(0).dot(&column(1).cross(&column(2)))
+    }
+
+    pub(crate) fn is_identity(&self) -> bool {
+        *self == Self::identity()
+    }
+
+    pub(crate) fn transform_point(&self, p: Point3) -> Point3 {
+        self.transform_vector(p) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    // Applies only the linear part, ignoring translation
+    pub(crate) fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Mul for Matrix {
+    type Output = Matrix;
+
+    fn mul(self, rhs: Matrix) -> Matrix {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix::new(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        matrix::Matrix,
+        vec3::{Point3, Vec3},
+    };
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn rotates_counter_clockwise() {
+        let r = Matrix::rotation(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        assert_near(
+            r.transform_vector(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let r = Matrix::rotation(Vec3::new(0.0, 1.0, 0.0), 90.0);
+        assert_near(
+            r.transform_vector(Vec3::new(0.0, 0.0, 1.0)),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn composes_and_inverts() {
+        let m = Matrix::translation(Vec3::new(1.0, 2.0, 3.0))
+            * Matrix::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Matrix::scaling(Vec3::new(2.0, 0.5, 3.0));
+        let p = Point3::new(0.3, -0.7, 1.1);
+        let inverse = m.inverse().unwrap();
+        assert_near(inverse.transform_point(m.transform_point(p)), p);
+        assert_near((m * inverse).transform_point(p), p);
+
+        assert_eq!(Matrix::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse(), None);
+    }
+}
@@ -0,0 +1,351 @@
+use std::sync::OnceLock;
+
+use crate::{
+    aabb::Aabb,
+    bvh::{partition, sah_bucket, sah_split},
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    ray::Ray,
+    sampler::Sampler,
+    triangle::TriangleMesh,
+    vec3::{Point3, Vec3},
+};
+
+// Faces per leaf below which a node is no longer split
+const MAX_LEAF_FACES: usize = 4;
+
+// Bound on the depth of the hierarchy, which sizes the traversal stack. Past SAH_DEPTH nodes
+// are split at the median, which needs at most 31 more levels for any u32 face count.
+const MAX_DEPTH: usize = 64;
+const SAH_DEPTH: usize = MAX_DEPTH - 32;
+
+// A node in the flattened hierarchy. Interior nodes keep their left child immediately after
+// themselves and store the index of the right child in first; leaves store count faces
+// starting at first in the face order.
+struct Node {
+    bbox: Aabb,
+    first: u32,
+    count: u32,
+    axis: u8,
+}
+
+// A triangle mesh together with its own bounding volume hierarchy over face indices. Unlike
+// wrapping every face in a hittable for BvhNode, this needs no allocation per triangle, which
+// keeps meshes with millions of faces compact.
+pub(crate) struct MeshBvh {
+    mesh: TriangleMesh,
+    nodes: Vec<Node>,
+    faces: Vec<u32>,
+    // Running totals of the face areas, built the first time the mesh is sampled as a light
+    areas: OnceLock<Vec<f64>>,
+}
+
+impl MeshBvh {
+    pub(crate) fn new(mesh: TriangleMesh) -> Self {
+        let face_count = mesh.face_count() as u32;
+        let bounds: Vec<Aabb> = (0..face_count)
+            .map(|face| {
+                let p = mesh.face_positions(face);
+                Aabb::surrounding(
+                    &Aabb::from_points(p[0], p[1]),
+                    &Aabb::from_points(p[2], p[2]),
+                )
+            })
+            .collect();
+
+        let mut bvh = Self {
+            mesh,
+            nodes: Vec::with_capacity(2 * face_count as usize / MAX_LEAF_FACES + 1),
+            faces: (0..face_count).collect(),
+            areas: OnceLock::new(),
+        };
+        let mut faces = std::mem::take(&mut bvh.faces);
+        if faces.is_empty() {
+            bvh.nodes.push(Node {
+                bbox: Aabb::empty(),
+                first: 0,
+                count: 0,
+                axis: 0,
+            });
+        } else {
+            bvh.build(&mut faces, 0, &bounds, 0);
+        }
+        bvh.faces = faces;
+        bvh
+    }
+
+    // The closest face r hits within ray_t, and the hit
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<(u32, HitRecord)> {
+        if self.faces.is_empty() {
+            return None;
+        }
+        let mut closest = None;
+        let mut ray_t = *ray_t;
+        // Each level leaves at most one far child pending, so the stack never outgrows the depth
+        let mut stack = [0usize; MAX_DEPTH + 1];
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let index = stack[len];
+            let node = &self.nodes[index];
+            if !node.bbox.hit(r, &ray_t) {
+                continue;
+            }
+            if node.count > 0 {
+                let first = node.first as usize;
+                for &face in &self.faces[first..first + node.count as usize] {
+                    if let Some(rec) = self.mesh.face_hit(face, r, &ray_t) {
+                        ray_t.max = rec.t;
+                        closest = Some((face, rec));
+                    }
+                }
+            } else {
+                // Visit the child nearer the ray origin first so later boxes can be culled
+                let (near, far) = if r.direction()[node.axis as usize] < 0.0 {
+                    (node.first as usize, index + 1)
+                } else {
+                    (index + 1, node.first as usize)
+                };
+                stack[len] = far;
+                stack[len + 1] = near;
+                len += 2;
+            }
+        }
+        closest
+    }
+
+    // None for meshes with no area to sample lights from
+    fn total_area(&self) -> Option<f64> {
+        self.areas().last().copied().filter(|&total| total > 0.0)
+    }
+
+    fn areas(&self) -> &[f64] {
+        self.areas.get_or_init(|| {
+            (0..self.mesh.face_count() as u32)
+                .scan(0.0, |total, face| {
+                    *total += self.mesh.face_normal_and_area(face).1;
+                    Some(*total)
+                })
+                .collect()
+        })
+    }
+
+    // Builds the subtree for faces, which sit at offset in the final face order and depth in
+    // the tree, returning the index of its root node
+    fn build(&mut self, faces: &mut [u32], offset: usize, bounds: &[Aabb], depth: usize) -> usize {
+        let bbox = faces.iter().fold(Aabb::empty(), |bbox, &f| {
+            Aabb::surrounding(&bbox, &bounds[f as usize])
+        });
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            bbox,
+            first: offset as u32,
+            count: faces.len() as u32,
+            axis: 0,
+        });
+        if faces.len() <= MAX_LEAF_FACES {
+            return index;
+        }
+
+        let centroid_bounds = faces.iter().fold(Aabb::empty(), |cb, &f| {
+            let c = bounds[f as usize].centroid();
+            Aabb::surrounding(&cb, &Aabb::from_points(c, c))
+        });
+        let axis = centroid_bounds.longest_axis();
+        let extent = *centroid_bounds.axis_interval(axis);
+
+        let mid = (depth < SAH_DEPTH)
+            .then(|| sah_split(faces, |&f| bounds[f as usize], &extent, axis, &bbox))
+            .flatten()
+            .map(|bucket| {
+                partition(faces, |&f| {
+                    sah_bucket(bounds[f as usize].centroid()[axis], &extent) < bucket
+                })
+            })
+            .unwrap_or_else(|| {
+                let mid = faces.len() / 2;
+                faces.select_nth_unstable_by(mid, |&a, &b| {
+                    let a = bounds[a as usize].centroid()[axis];
+                    let b = bounds[b as usize].centroid()[axis];
+                    a.total_cmp(&b)
+                });
+                mid
+            });
+
+        let (left, right) = faces.split_at_mut(mid);
+        self.build(left, offset, bounds, depth + 1);
+        let right_index = self.build(right, offset + mid, bounds, depth + 1);
+        let node = &mut self.nodes[index];
+        node.first = right_index as u32;
+        node.count = 0;
+        node.axis = axis as u8;
+        index
+    }
+}
+
+impl Hittable for MeshBvh {
+    fn hit(&self, r: &Ray, ray_t: &Interval, _sampler: &mut Sampler) -> Option<HitRecord> {
+        self.intersect(r, ray_t).map(|(_, rec)| rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes[0].bbox
+    }
+
+    // Points are chosen uniformly over the whole area, and the line towards one may cross other
+    // faces first, so every face along direction adds to the density
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let Some(total) = self.total_area() else {
+            return 0.0;
+        };
+        let r = Ray::new(*origin, *direction);
+        let mut ray_t = Interval::new(0.001, f64::INFINITY);
+        let mut pdf = 0.0;
+        while let Some((face, rec)) = self.intersect(&r, &ray_t) {
+            let (normal, _) = self.mesh.face_normal_and_area(face);
+            let distance_squared = rec.t * rec.t * direction.length_squared();
+            let cosine = (direction.dot(&normal) / direction.length()).abs();
+            pdf += distance_squared / (cosine * total);
+            ray_t.min = rec.t;
+        }
+        pdf
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        match self.sample_surface(sampler) {
+            Some((rec, _)) => rec.p - *origin,
+            None => Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    fn sample_surface(&self, sampler: &mut Sampler) -> Option<(HitRecord, f64)> {
+        let total = self.total_area()?;
+        let areas = self.areas();
+        let target = sampler.random_f64() * total;
+        let face = areas
+            .partition_point(|&area| area <= target)
+            .min(areas.len() - 1);
+        // Uniform over the face
+        let s = sampler.random_f64().sqrt();
+        let t = sampler.random_f64();
+        let rec = self.mesh.face_point(face as u32, s * (1.0 - t), s * t);
+        Some((rec, 1.0 / total))
+    }
+
+    // Points within a small tolerance of a face, allowing for rounding in the hits that find
+    // them, count as on the mesh
+    fn surface_pdf(&self, p: &Point3, normal: &Vec3) -> f64 {
+        let Some(total) = self.total_area() else {
+            return 0.0;
+        };
+        let tolerance = 1e-6 * (1.0 + p.length());
+        let n = normal.unit_vector();
+        let probe = Ray::new(*p + tolerance * n, -n);
+        match self.intersect(&probe, &Interval::new(0.0, 2.0 * tolerance)) {
+            Some(_) => 1.0 / total,
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        hittable_list::HittableList,
+        interval::Interval,
+        material::Lambertian,
+        mesh_bvh::MeshBvh,
+        quad::Quad,
+        ray::Ray,
+        sampler::Sampler,
+        triangle::TriangleMesh,
+        vec3::{Point3, Vec3},
+    };
+
+    fn random_mesh(sampler: &mut Sampler, faces: u32) -> TriangleMesh {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let positions: Vec<Point3> = (0..faces)
+            .flat_map(|_| {
+                let corner = Point3::random_range(sampler, -10.0, 10.0);
+                [
+                    corner,
+                    corner + Vec3::random_range(sampler, -1.0, 1.0),
+                    corner + Vec3::random_range(sampler, -1.0, 1.0),
+                ]
+            })
+            .collect();
+        let indices = (0..faces).map(|f| [3 * f, 3 * f + 1, 3 * f + 2]).collect();
+        TriangleMesh::new(positions, Vec::new(), Vec::new(), Vec::new(), indices, mat)
+    }
+
+    #[test]
+    fn matches_per_face_hittables() {
+        let mut flat = HittableList::empty();
+        for triangle in Arc::new(random_mesh(&mut Sampler::new(3), 500)).triangles() {
+            flat.add(triangle);
+        }
+        let bvh = MeshBvh::new(random_mesh(&mut Sampler::new(3), 500));
+
+        let mut sampler = Sampler::new(4);
+        for _ in 0..2000 {
+            let r = Ray::new(
+                Point3::random_range(&mut sampler, -15.0, 15.0),
+                Vec3::random_unit_vector(&mut sampler),
+            );
+            let ray_t = Interval::new(0.001, f64::INFINITY);
+            let expected = flat.hit(&r, &ray_t, &mut sampler).map(|rec| rec.t);
+            let actual = bvh.hit(&r, &ray_t, &mut sampler).map(|rec| rec.t);
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn empty_mesh_never_hits() {
+        let mut sampler = Sampler::new(3);
+        let bvh = MeshBvh::new(random_mesh(&mut sampler, 0));
+        let r = Ray::new(Point3::origin(), Vec3::new(0.0, 0.0, -1.0));
+        assert!(bvh
+            .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .is_none());
+    }
+    #[test]
+    fn samples_like_a_quad() {
+        // The square from the origin to (2, 3) in the z = 0 plane, as a quad and as two faces
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let quad = Quad::new(
+            Point3::origin(),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            mat.clone(),
+        );
+        let positions = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(2.0, 3.0, 0.0),
+            Point3::new(0.0, 3.0, 0.0),
+        ];
+        let faces = vec![[0, 1, 2], [0, 2, 3]];
+        let mesh = TriangleMesh::new(positions, Vec::new(), Vec::new(), Vec::new(), faces, mat);
+        let bvh = MeshBvh::new(mesh);
+
+        let mut sampler = Sampler::new(5);
+        let origin = Point3::new(1.0, 1.0, 4.0);
+        for _ in 0..100 {
+            let direction = bvh.random(&origin, &mut sampler);
+            let expected = quad.pdf_value(&origin, &direction);
+            assert!((bvh.pdf_value(&origin, &direction) - expected).abs() < 1e-9 * expected);
+
+            let (rec, pdf) = bvh.sample_surface(&mut sampler).unwrap();
+            assert_eq!(pdf, 1.0 / 6.0);
+            assert_eq!(rec.p.z(), 0.0);
+            assert_eq!(bvh.surface_pdf(&rec.p, &rec.normal), 1.0 / 6.0);
+        }
+        let outside = Point3::new(2.5, 1.0, 0.0);
+        assert_eq!(bvh.surface_pdf(&outside, &Vec3::new(0.0, 0.0, 1.0)), 0.0);
+    }
+}
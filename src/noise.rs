@@ -0,0 +1,192 @@
+use crate::{
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+// Size of the random tables behind both noise functions; the noise repeats every this many
+// units along each axis
+const POINT_COUNT: usize = 256;
+
+// Shuffled permutations for hashing integer lattice points onto the random tables
+struct Lattice {
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Lattice {
+    fn new(sampler: &mut Sampler) -> Self {
+        Self {
+            perm_x: permutation(sampler),
+            perm_y: permutation(sampler),
+            perm_z: permutation(sampler),
+        }
+    }
+
+    fn hash(&self, i: i64, j: i64, k: i64) -> usize {
+        let mask = POINT_COUNT as i64 - 1;
+        self.perm_x[(i & mask) as usize]
+            ^ self.perm_y[(j & mask) as usize]
+            ^ self.perm_z[(k & mask) as usize]
+    }
+}
+
+// A Fisher-Yates shuffle of 0..POINT_COUNT
+fn permutation(sampler: &mut Sampler) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let target = (sampler.random_f64() * (i + 1) as f64) as usize;
+        p.swap(i, target.min(i));
+    }
+    p
+}
+
+// Ken Perlin's gradient noise: smooth, band limited values in roughly [-1, 1] that are zero
+// at every integer lattice point
+pub(crate) struct Perlin {
+    gradients: Vec<Vec3>,
+    lattice: Lattice,
+}
+
+impl Perlin {
+    pub(crate) fn new(sampler: &mut Sampler) -> Self {
+        Self {
+            gradients: (0..POINT_COUNT)
+                .map(|_| Vec3::random_unit_vector(sampler))
+                .collect(),
+            lattice: Lattice::new(sampler),
+        }
+    }
+
+    pub(crate) fn noise(&self, p: &Point3) -> f64 {
+        let (i, j, k) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - i, p.y() - j, p.z() - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        // Hermite smoothing hides the lattice
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.lattice.hash(i + di, j + dj, k + dk)];
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // Fractal Brownian motion: octaves of noise at doubling frequency and halving amplitude,
+    // normalized back to the range of a single octave
+    pub(crate) fn fbm(&self, p: &Point3, octaves: usize) -> f64 {
+        let mut accum = 0.0;
+        let mut total = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves.max(1) {
+            accum += weight * self.noise(&temp_p);
+            total += weight;
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum / total
+    }
+
+    // Sums the magnitude of each octave, giving the creased look of turbulent flow
+    pub(crate) fn turbulence(&self, p: &Point3, depth: usize) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth.max(1) {
+            accum += weight * self.noise(&temp_p).abs();
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum
+    }
+}
+
+// Steven Worley's cellular noise: every unit cell holds one random feature point, and the noise
+// is the distance to the nearest one
+pub(crate) struct Worley {
+    points: Vec<Vec3>,
+    lattice: Lattice,
+}
+
+impl Worley {
+    pub(crate) fn new(sampler: &mut Sampler) -> Self {
+        Self {
+            points: (0..POINT_COUNT).map(|_| Vec3::random(sampler)).collect(),
+            lattice: Lattice::new(sampler),
+        }
+    }
+
+    // The distance from p to the closest feature point, mostly within [0, 1]
+    pub(crate) fn noise(&self, p: &Point3) -> f64 {
+        let (i, j, k) = (
+            p.x().floor() as i64,
+            p.y().floor() as i64,
+            p.z().floor() as i64,
+        );
+        let mut nearest = f64::INFINITY;
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let (ci, cj, ck) = (i + di, j + dj, k + dk);
+                    let cell = Point3::new(ci as f64, cj as f64, ck as f64);
+                    let feature = cell + self.points[self.lattice.hash(ci, cj, ck)];
+                    nearest = nearest.min((feature - *p).length_squared());
+                }
+            }
+        }
+        nearest.sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        noise::{Perlin, Worley},
+        sampler::Sampler,
+        vec3::Point3,
+    };
+
+    #[test]
+    fn noise_is_seeded_and_smooth() {
+        let perlin = Perlin::new(&mut Sampler::new(1));
+        let p = Point3::new(1.3, -2.7, 0.4);
+        assert_eq!(
+            perlin.noise(&p),
+            Perlin::new(&mut Sampler::new(1)).noise(&p)
+        );
+        assert_ne!(
+            perlin.noise(&p),
+            Perlin::new(&mut Sampler::new(2)).noise(&p)
+        );
+        assert_eq!(perlin.noise(&Point3::new(3.0, -1.0, 7.0)), 0.0);
+
+        let nearby = p + Point3::new(1e-4, 0.0, 0.0);
+        assert!((perlin.noise(&p) - perlin.noise(&nearby)).abs() < 1e-3);
+        assert!(perlin.fbm(&p, 5).abs() <= 1.0);
+        assert!(perlin.turbulence(&p, 7) >= 0.0);
+    }
+
+    #[test]
+    fn worley_measures_distance_to_features() {
+        let worley = Worley::new(&mut Sampler::new(1));
+        for x in 0..20 {
+            let p = Point3::new(x as f64 * 0.37, 0.5, -1.25);
+            let d = worley.noise(&p);
+            // Every point is within one cell diagonal of its own cell's feature
+            assert!((0.0..=3f64.sqrt()).contains(&d));
+        }
+    }
+}
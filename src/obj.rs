@@ -0,0 +1,495 @@
+// Wavefront OBJ and MTL import. Supported OBJ statements are v, vn, vt, f, g, o, usemtl and
+// mtllib; faces with more than three vertices are triangulated as fans, and negative indices
+// count back from the most recent vertex. Other statements (lines, smoothing groups, free-form
+// geometry) are ignored. Each run of faces sharing a group and material becomes one
+// TriangleMesh with its own MeshBvh.
+//
+// MTL materials are mapped onto the crate's materials by their most prominent property:
+//   Ke > 0                 DiffuseLight emitting Ke
+//   d < 1 (or Tr > 0)      Dielectric with refraction index Ni
+//   max(Ks) > max(Kd)      Metal with albedo Ks, fuzz derived from the Ns specular exponent
+//   otherwise              Lambertian with albedo Kd
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::SplitWhitespace,
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    hittable_list::HittableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    mesh_bvh::MeshBvh,
+    triangle::{TriangleMesh, Uv},
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub(crate) enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    // A problem with the contents of an OBJ or MTL file at the given 1-based line
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {error}", path.display()),
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{file}:{line}: {message}"),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+pub(crate) type MaterialLibrary = HashMap<String, Arc<dyn Material>>;
+
+// Loads an OBJ file along with any MTL libraries it references. Faces without a material, or
+// every face when override_mat is given, use that material (or a default grey).
+pub(crate) fn load_obj(
+    path: &Path,
+    override_mat: Option<Arc<dyn Material>>,
+) -> Result<HittableList, ObjError> {
+    let source = read(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let load_library = |name: &str| {
+        let mtl_path = base_dir.join(name);
+        parse_mtl(&read(&mtl_path)?, &mtl_path.display().to_string())
+    };
+    parse_obj(
+        &source,
+        &path.display().to_string(),
+        load_library,
+        override_mat,
+    )
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+// One mesh under construction: vertices are deduplicated on their position/uv/normal indices
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Uv>,
+    has_normals: bool,
+    has_uvs: bool,
+    faces: Vec<[u32; 3]>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, key: (usize, Option<usize>, Option<usize>), obj: &ObjData) -> u32 {
+        if let Some(&index) = self.vertices.get(&key) {
+            return index;
+        }
+        let (v, vt, vn) = key;
+        self.positions.push(obj.positions[v]);
+        self.uvs.push(vt.map_or((0.0, 0.0), |vt| obj.uvs[vt]));
+        self.normals
+            .push(vn.map_or(Vec3::origin(), |vn| obj.normals[vn]));
+        self.has_uvs |= vt.is_some();
+        self.has_normals |= vn.is_some();
+        let index = self.positions.len() as u32 - 1;
+        self.vertices.insert(key, index);
+        index
+    }
+
+    fn build(self, mat: Arc<dyn Material>) -> TriangleMesh {
+        let normals = if self.has_normals {
+            self.normals
+        } else {
+            Vec::new()
+        };
+        let uvs = if self.has_uvs { self.uvs } else { Vec::new() };
+        TriangleMesh::new(self.positions, normals, uvs, Vec::new(), self.faces, mat)
+    }
+}
+
+#[derive(Default)]
+struct ObjData {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Uv>,
+}
+
+pub(crate) fn parse_obj(
+    source: &str,
+    file: &str,
+    mut load_library: impl FnMut(&str) -> Result<MaterialLibrary, ObjError>,
+    override_mat: Option<Arc<dyn Material>>,
+) -> Result<HittableList, ObjError> {
+    let default_mat: Arc<dyn Material> = match override_mat.clone() {
+        Some(mat) => mat,
+        None => Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+    };
+
+    let mut obj = ObjData::default();
+    let mut library = MaterialLibrary::new();
+    let mut world = HittableList::empty();
+    let mut current_mat = default_mat.clone();
+    let mut mesh = MeshBuilder::default();
+
+    let mut finish_mesh = |mesh: MeshBuilder, mat: &Arc<dyn Material>| {
+        if !mesh.faces.is_empty() {
+            world.add(Arc::new(MeshBvh::new(mesh.build(mat.clone()))));
+        }
+    };
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| ObjError::Parse {
+            file: file.to_string(),
+            line,
+            message,
+        };
+        let text = text.split('#').next().unwrap_or_default();
+        let mut tokens = text.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        match keyword {
+            "v" => obj
+                .positions
+                .push(parse_vec3(&mut tokens, keyword).map_err(error)?),
+            "vn" => obj
+                .normals
+                .push(parse_vec3(&mut tokens, keyword).map_err(error)?),
+            "vt" => {
+                let u = parse_number(tokens.next(), keyword).map_err(error)?;
+                let v = match tokens.next() {
+                    Some(v) => parse_number(Some(v), keyword).map_err(error)?,
+                    None => 0.0,
+                };
+                obj.uvs.push((u, v));
+            }
+            "f" => {
+                let corners = tokens
+                    .map(|corner| parse_corner(corner, &obj))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+                if corners.len() < 3 {
+                    return Err(error(format!(
+                        "a face needs at least 3 vertices, found {}",
+                        corners.len()
+                    )));
+                }
+                let indices: Vec<u32> = corners
+                    .into_iter()
+                    .map(|corner| mesh.vertex(corner, &obj))
+                    .collect();
+                for i in 1..indices.len() - 1 {
+                    mesh.faces.push([indices[0], indices[i], indices[i + 1]]);
+                }
+            }
+            "g" | "o" => {
+                finish_mesh(std::mem::take(&mut mesh), &current_mat);
+            }
+            "usemtl" => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| error(String::from("usemtl needs a material name")))?;
+                finish_mesh(std::mem::take(&mut mesh), &current_mat);
+                if override_mat.is_none() {
+                    current_mat = library
+                        .get(name)
+                        .cloned()
+                        .ok_or_else(|| error(format!("unknown material '{name}'")))?;
+                }
+            }
+            // Material libraries are irrelevant when every face uses the override
+            "mtllib" if override_mat.is_none() => {
+                for name in tokens {
+                    library.extend(load_library(name)?);
+                }
+            }
+            _ => {}
+        }
+    }
+    finish_mesh(mesh, &current_mat);
+
+    Ok(world)
+}
+
+fn parse_number(token: Option<&str>, keyword: &str) -> Result<f64, String> {
+    let token = token.ok_or_else(|| format!("'{keyword}' is missing a value"))?;
+    token
+        .parse()
+        .map_err(|_| format!("'{keyword}' expects numbers, found '{token}'"))
+}
+
+fn parse_vec3(tokens: &mut SplitWhitespace, keyword: &str) -> Result<Vec3, String> {
+    Ok(Vec3::new(
+        parse_number(tokens.next(), keyword)?,
+        parse_number(tokens.next(), keyword)?,
+        parse_number(tokens.next(), keyword)?,
+    ))
+}
+
+// Resolves one "v", "v/vt", "v//vn" or "v/vt/vn" face corner to zero-based indices
+fn parse_corner(
+    corner: &str,
+    obj: &ObjData,
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let mut parts = corner.split('/');
+    let v = resolve_index(parts.next(), obj.positions.len(), "vertex", corner)?
+        .ok_or_else(|| format!("face vertex '{corner}' has no position index"))?;
+    let vt = resolve_index(parts.next(), obj.uvs.len(), "texture coordinate", corner)?;
+    let vn = resolve_index(parts.next(), obj.normals.len(), "normal", corner)?;
+    if parts.next().is_some() {
+        return Err(format!("malformed face vertex '{corner}'"));
+    }
+    Ok((v, vt, vn))
+}
+
+fn resolve_index(
+    part: Option<&str>,
+    count: usize,
+    kind: &str,
+    corner: &str,
+) -> Result<Option<usize>, String> {
+    let Some(part) = part.filter(|p| !p.is_empty()) else {
+        return Ok(None);
+    };
+    let index: i64 = part
+        .parse()
+        .map_err(|_| format!("malformed face vertex '{corner}'"))?;
+    // OBJ indices are 1-based; negative ones are relative to the end of the list so far
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{kind} index {index} in '{corner}' is out of range, {count} defined so far"
+        ));
+    }
+    Ok(Some(resolved as usize))
+}
+
+#[derive(Clone)]
+struct MtlProperties {
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ns: f64,
+    ni: f64,
+    d: f64,
+}
+
+impl Default for MtlProperties {
+    fn default() -> Self {
+        Self {
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::origin(),
+            ke: Color::origin(),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+        }
+    }
+}
+
+impl MtlProperties {
+    fn to_material(&self) -> Arc<dyn Material> {
+        let max = |c: &Color| c.x().max(c.y()).max(c.z());
+        if max(&self.ke) > 0.0 {
+            Arc::new(DiffuseLight::new(self.ke))
+        } else if self.d < 1.0 {
+            Arc::new(Dielectric::new(self.ni))
+        } else if max(&self.ks) > max(&self.kd) {
+            // Map the Phong exponent onto a roughness: sharp highlights give polished metal
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt();
+            Arc::new(Metal::new(self.ks, fuzz))
+        } else {
+            Arc::new(Lambertian::new(self.kd))
+        }
+    }
+}
+
+pub(crate) fn parse_mtl(source: &str, file: &str) -> Result<MaterialLibrary, ObjError> {
+    let mut library = MaterialLibrary::new();
+    let mut current: Option<(String, MtlProperties)> = None;
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| ObjError::Parse {
+            file: file.to_string(),
+            line,
+            message,
+        };
+        let text = text.split('#').next().unwrap_or_default();
+        let mut tokens = text.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens
+                .next()
+                .ok_or_else(|| error(String::from("newmtl needs a material name")))?;
+            if let Some((name, properties)) = current.take() {
+                library.insert(name, properties.to_material());
+            }
+            current = Some((name.to_string(), MtlProperties::default()));
+            continue;
+        }
+
+        let properties = match &mut current {
+            Some((_, properties)) => properties,
+            None if matches!(keyword, "Kd" | "Ks" | "Ke" | "Ns" | "Ni" | "d" | "Tr") => {
+                return Err(error(format!("'{keyword}' appears before any newmtl")))
+            }
+            None => continue,
+        };
+        match keyword {
+            "Kd" => properties.kd = parse_vec3(&mut tokens, keyword).map_err(error)?,
+            "Ks" => properties.ks = parse_vec3(&mut tokens, keyword).map_err(error)?,
+            "Ke" => properties.ke = parse_vec3(&mut tokens, keyword).map_err(error)?,
+            "Ns" => properties.ns = parse_number(tokens.next(), keyword).map_err(error)?,
+            "Ni" => properties.ni = parse_number(tokens.next(), keyword).map_err(error)?,
+            "d" => properties.d = parse_number(tokens.next(), keyword).map_err(error)?,
+            "Tr" => properties.d = 1.0 - parse_number(tokens.next(), keyword).map_err(error)?,
+            // Illumination models, texture maps and the like are not supported
+            _ => {}
+        }
+    }
+    if let Some((name, properties)) = current {
+        library.insert(name, properties.to_material());
+    }
+
+    Ok(library)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        interval::Interval,
+        material::DiffuseLight,
+        obj::{parse_mtl, parse_obj, MaterialLibrary, ObjError},
+        ray::Ray,
+        sampler::Sampler,
+        vec3::{Point3, Vec3},
+    };
+
+    const QUAD_OBJ: &str = "\
+# a unit square split into two groups
+mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+g square
+usemtl lamp
+f 1/1/1 2/2/1 3/3/1 4/4/1
+g copy
+f -4//-1 -3//-1 -2//-1
+";
+
+    fn no_libraries(name: &str) -> Result<MaterialLibrary, ObjError> {
+        panic!("unexpected mtllib {name}")
+    }
+
+    fn error_of(result: Result<impl Sized, ObjError>) -> (usize, String) {
+        match result {
+            Err(ObjError::Parse { line, message, .. }) => (line, message),
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn parses_faces_groups_and_materials() {
+        let mut sampler = Sampler::new(0);
+        let library = |name: &str| {
+            assert_eq!(name, "scene.mtl");
+            parse_mtl("newmtl lamp\nKe 2 2 2\n", name)
+        };
+        let world = parse_obj(QUAD_OBJ, "quad.obj", library, None).unwrap();
+
+        // The quad is fanned into two triangles, and the second group gets its own mesh
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        let r = Ray::new(Point3::new(0.25, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = world.hit(&r, &ray_t, &mut sampler).unwrap();
+        assert_eq!((rec.u, rec.v), (0.25, 0.75));
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        let emitted = rec.mat.as_ref().unwrap().emitted(&r, &rec);
+        assert_eq!(emitted, Color::new(2.0, 2.0, 2.0));
+        assert_eq!(world.into_objects().len(), 2);
+    }
+
+    #[test]
+    fn override_material_skips_libraries() {
+        let lamp = Arc::new(DiffuseLight::new(Color::new(1.0, 0.0, 0.0)));
+        let world = parse_obj(QUAD_OBJ, "quad.obj", no_libraries, Some(lamp)).unwrap();
+        assert_eq!(world.into_objects().len(), 2);
+    }
+
+    #[test]
+    fn reports_malformed_lines() {
+        let library = |name: &str| parse_mtl("newmtl lamp\nKe 1 1 1\n", name);
+        let parse = |source: &str| parse_obj(source, "bad.obj", library, None);
+        assert_eq!(
+            error_of(parse("v 0 0 0\nv 1 0\n")),
+            (2, String::from("'v' is missing a value"))
+        );
+        assert_eq!(
+            error_of(parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n")),
+            (
+                4,
+                String::from("vertex index 4 in '4' is out of range, 3 defined so far")
+            )
+        );
+        assert_eq!(
+            error_of(parse("v 0 0 0\nv 1 0 0\nf 1 2\n")),
+            (3, String::from("a face needs at least 3 vertices, found 2"))
+        );
+        assert_eq!(
+            error_of(parse("v 0 0 0\nf 1/x 1 1\n")),
+            (2, String::from("malformed face vertex '1/x'"))
+        );
+        assert_eq!(
+            error_of(parse("mtllib a.mtl\nusemtl brass\n")),
+            (2, String::from("unknown material 'brass'"))
+        );
+        assert_eq!(
+            error_of(parse_mtl("newmtl a\nKd 1 one 1\n", "bad.mtl")),
+            (2, String::from("'Kd' expects numbers, found 'one'"))
+        );
+        assert_eq!(
+            error_of(parse_mtl("Ns 10\n", "bad.mtl")),
+            (1, String::from("'Ns' appears before any newmtl"))
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use crate::vec3::Vec3;
+
+// An orthonormal basis built around a direction w, for sampling directions relative to it
+pub(crate) struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub(crate) fn new(n: Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        Self { axis: [u, v, w] }
+    }
+
+    pub(crate) fn w(&self) -> Vec3 {
+        self.axis[2]
+    }
+
+    // Maps a vector given in basis coordinates to world coordinates
+    pub(crate) fn transform(&self, v: Vec3) -> Vec3 {
+        v.x() * self.axis[0] + v.y() * self.axis[1] + v.z() * self.axis[2]
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    color::{to_bytes, to_words, Color},
+    framebuffer::Framebuffer,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ImageFormat {
+    // Plain text P3 PPM, one pixel per line
+    PpmAscii,
+    // Raw P6 PPM with 8 bits per channel
+    PpmBinary,
+    Png8,
+    Png16,
+    // Radiance RGBE holding the unclamped linear radiance
+    Hdr,
+    // Uncompressed scanline OpenEXR with 32 bit float linear channels
+    Exr,
+}
+
+impl ImageFormat {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ppm" | "p3" => Some(Self::PpmAscii),
+            "ppm-binary" | "p6" => Some(Self::PpmBinary),
+            "png" | "png8" => Some(Self::Png8),
+            "png16" => Some(Self::Png16),
+            "hdr" => Some(Self::Hdr),
+            "exr" => Some(Self::Exr),
+            _ => None,
+        }
+    }
+
+    // Guesses the format from a file extension, preferring the 8 bit variant of each container
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(Self::PpmAscii),
+            "png" => Some(Self::Png8),
+            "hdr" => Some(Self::Hdr),
+            "exr" => Some(Self::Exr),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn save(image: &Framebuffer, path: &Path, format: ImageFormat) -> io::Result<()> {
+    let mut buffer = BufWriter::new(File::create(path)?);
+    encode(image, format, &mut buffer)?;
+    buffer.flush()
+}
+
+pub(crate) fn encode(image: &Framebuffer, format: ImageFormat, out: impl Write) -> io::Result<()> {
+    match format {
+        ImageFormat::PpmAscii => write_ppm_ascii(image, out),
+        ImageFormat::PpmBinary => write_ppm_binary(image, out),
+        ImageFormat::Png8 => write_png(image, png::BitDepth::Eight, out),
+        ImageFormat::Png16 => write_png(image, png::BitDepth::Sixteen, out),
+        ImageFormat::Hdr => write_hdr(image, out),
+        ImageFormat::Exr => write_exr(image, out),
+    }
+}
+
+fn write_ppm_ascii(image: &Framebuffer, mut out: impl Write) -> io::Result<()> {
+    write!(out, "P3\n{0} {1}\n255\n", image.width(), image.height())?;
+    for &pixel_color in image.pixels() {
+        let [rbyte, gbyte, bbyte] = to_bytes(pixel_color);
+        writeln!(out, "{rbyte} {gbyte} {bbyte}")?;
+    }
+    Ok(())
+}
+
+fn write_ppm_binary(image: &Framebuffer, mut out: impl Write) -> io::Result<()> {
+    write!(out, "P6\n{0} {1}\n255\n", image.width(), image.height())?;
+    let bytes: Vec<u8> = image.pixels().iter().flat_map(|&c| to_bytes(c)).collect();
+    out.write_all(&bytes)
+}
+
+fn write_png(image: &Framebuffer, bit_depth: png::BitDepth, out: impl Write) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(bit_depth);
+    // Pixel values are gamma 2.0 encoded by linear_to_gamma
+    encoder.set_source_gamma(png::ScaledFloat::new(0.5));
+
+    let data: Vec<u8> = match bit_depth {
+        png::BitDepth::Sixteen => image
+            .pixels()
+            .iter()
+            .flat_map(|&c| to_words(c))
+            .flat_map(u16::to_be_bytes)
+            .collect(),
+        _ => image.pixels().iter().flat_map(|&c| to_bytes(c)).collect(),
+    };
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_hdr(image: &Framebuffer, mut out: impl Write) -> io::Result<()> {
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {0} +X {1}\n",
+        image.height(),
+        image.width()
+    )?;
+    // Scanlines are written flat rather than run-length encoded, which every reader accepts
+    let bytes: Vec<u8> = image.pixels().iter().flat_map(|&c| to_rgbe(c)).collect();
+    out.write_all(&bytes)
+}
+
+// Shared exponent encoding: each channel keeps 8 bits of mantissa relative to the brightest one.
+// Channels brighter than the format can hold, including infinite ones, are written as its
+// largest value, 255/256 * 2^127, and NaNs as 0.
+fn to_rgbe(pixel_color: Color) -> [u8; 4] {
+    let largest = 255.0 / 256.0 * 2f64.powi(127);
+    let clamp = |c: f64| {
+        if c.is_nan() {
+            0.0
+        } else {
+            c.clamp(0.0, largest)
+        }
+    };
+    let (r, g, b) = (
+        clamp(pixel_color.x()),
+        clamp(pixel_color.y()),
+        clamp(pixel_color.z()),
+    );
+    let v = r.max(g).max(b);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // v = m * 2^e with m in [0.5, 1)
+    let e = v.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f64.powi(e);
+    let channel = |c: f64| (c * scale).min(255.0) as u8;
+    [
+        channel(r),
+        channel(g),
+        channel(b),
+        (e + 128).clamp(0, 255) as u8,
+    ]
+}
+
+fn write_exr(image: &Framebuffer, mut out: impl Write) -> io::Result<()> {
+    let (width, height) = (image.width(), image.height());
+    let window = [0, 0, width as i32 - 1, height as i32 - 1];
+
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    // Channels must be listed in alphabetical order; each is FLOAT (2) and fully sampled
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&2i32.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    exr_attribute(&mut header, "channels", "chlist", &channels);
+    exr_attribute(&mut header, "compression", "compression", &[0]);
+    let window: Vec<u8> = window.iter().flat_map(|v| v.to_le_bytes()).collect();
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // Without compression every chunk holds one scanline: y, byte count, then each channel's row
+    let line_bytes = 3 * 4 * width;
+    let chunk_bytes = 8 + line_bytes;
+    let first_chunk = header.len() + 8 * height;
+    out.write_all(&header)?;
+    for y in 0..height {
+        out.write_all(&((first_chunk + y * chunk_bytes) as u64).to_le_bytes())?;
+    }
+
+    let mut chunk = Vec::with_capacity(chunk_bytes);
+    for (y, row) in image.pixels().chunks(width).enumerate() {
+        chunk.clear();
+        chunk.extend_from_slice(&(y as i32).to_le_bytes());
+        chunk.extend_from_slice(&(line_bytes as i32).to_le_bytes());
+        for channel in [2, 1, 0] {
+            for pixel_color in row {
+                chunk.extend_from_slice(&(pixel_color[channel] as f32).to_le_bytes());
+            }
+        }
+        out.write_all(&chunk)?;
+    }
+    Ok(())
+}
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        color::Color,
+        framebuffer::Framebuffer,
+        output::{encode, to_rgbe, ImageFormat},
+    };
+
+    fn gradient() -> Framebuffer {
+        let mut image = Framebuffer::new(3, 2);
+        let block: Vec<Color> = (0..6)
+            .map(|i| Color::new(i as f64 / 5.0, 0.25, 1.0))
+            .collect();
+        image.write_block(0, 0, 3, &block);
+        image
+    }
+
+    #[test]
+    fn ascii_ppm_layout() {
+        let mut out = Vec::new();
+        encode(&gradient(), ImageFormat::PpmAscii, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(&lines[..3], &["P3", "3 2", "255"]);
+        assert_eq!(lines.len(), 3 + 6);
+        assert_eq!(lines[3], "0 128 255");
+    }
+
+    #[test]
+    fn binary_ppm_matches_ascii() {
+        let (mut ascii, mut binary) = (Vec::new(), Vec::new());
+        encode(&gradient(), ImageFormat::PpmAscii, &mut ascii).unwrap();
+        encode(&gradient(), ImageFormat::PpmBinary, &mut binary).unwrap();
+
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&binary[..header.len()], header);
+        let ascii_values: Vec<u8> = String::from_utf8(ascii).unwrap()[11..]
+            .split_whitespace()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        assert_eq!(&binary[header.len()..], &ascii_values[..]);
+    }
+
+    #[test]
+    fn png_round_trips() {
+        for (format, depth) in [
+            (ImageFormat::Png8, png::BitDepth::Eight),
+            (ImageFormat::Png16, png::BitDepth::Sixteen),
+        ] {
+            let mut out = Vec::new();
+            encode(&gradient(), format, &mut out).unwrap();
+            let decoder = png::Decoder::new(&out[..]);
+            let reader = decoder.read_info().unwrap();
+            let info = reader.info();
+            assert_eq!((info.width, info.height), (3, 2));
+            assert_eq!(info.bit_depth, depth);
+            assert_eq!(info.color_type, png::ColorType::Rgb);
+        }
+    }
+
+    #[test]
+    fn rgbe_keeps_high_dynamic_range() {
+        assert_eq!(to_rgbe(Color::origin()), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(Color::new(1.0, 0.5, 0.25)), [128, 64, 32, 129]);
+
+        let [r, g, b, e] = to_rgbe(Color::new(40.0, 3.0, 0.0));
+        let scale = 2f64.powi(e as i32 - 128 - 8);
+        assert!((r as f64 * scale - 40.0).abs() < 40.0 / 128.0);
+        assert!((g as f64 * scale - 3.0).abs() < 40.0 / 128.0);
+        assert_eq!(b, 0);
+
+        // Past the largest exponent, and at infinity, channels saturate rather than wrap
+        assert_eq!(
+            to_rgbe(Color::new(2f64.powi(128), 1.0, 0.0)),
+            [255, 0, 0, 255]
+        );
+        assert_eq!(
+            to_rgbe(Color::new(f64::INFINITY, f64::NAN, 2f64.powi(126))),
+            [255, 0, 128, 255]
+        );
+    }
+
+    #[test]
+    fn hdr_layout() {
+        let mut out = Vec::new();
+        encode(&gradient(), ImageFormat::Hdr, &mut out).unwrap();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 3\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(out.len(), header.len() + 4 * 6);
+    }
+
+    #[test]
+    fn exr_scanlines_are_linear_floats() {
+        let mut image = gradient();
+        image.write_block(2, 1, 1, &[Color::new(12.5, 0.25, 3.0)]);
+        let mut out = Vec::new();
+        encode(&image, ImageFormat::Exr, &mut out).unwrap();
+        assert_eq!(&out[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        let read_u64 = |at: usize| u64::from_le_bytes(out[at..at + 8].try_into().unwrap());
+        let read_i32 = |at: usize| i32::from_le_bytes(out[at..at + 4].try_into().unwrap());
+        let read_f32 = |at: usize| f32::from_le_bytes(out[at..at + 4].try_into().unwrap());
+
+        // The offset table is the last thing before the first chunk
+        let line_bytes = 3 * 4 * 3;
+        let second = read_u64(out.len() - 2 * (8 + line_bytes) - 8) as usize;
+        assert_eq!(second, out.len() - (8 + line_bytes));
+        assert_eq!(read_i32(second), 1);
+        assert_eq!(read_i32(second + 4), line_bytes as i32);
+
+        // Channels are stored B, G, R for the whole row
+        let data = second + 8;
+        assert_eq!(read_f32(data + 2 * 4), 3.0);
+        assert_eq!(read_f32(data + 3 * 4 + 2 * 4), 0.25);
+        assert_eq!(read_f32(data + 6 * 4 + 2 * 4), 12.5);
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            ImageFormat::from_path(Path::new("out/render.PNG")),
+            Some(ImageFormat::Png8)
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("image.ppm")),
+            Some(ImageFormat::PpmAscii)
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("beauty.exr")),
+            Some(ImageFormat::Exr)
+        );
+        assert_eq!(ImageFormat::from_path(Path::new("image")), None);
+    }
+}
@@ -0,0 +1,254 @@
+use std::f64::consts::PI;
+
+use crate::{
+    hittable::Hittable,
+    onb::Onb,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+// A probability density over directions, for importance sampling where rays go next. value is
+// the density per unit solid angle of generate producing a direction.
+pub(crate) trait Pdf {
+    fn value(&self, direction: &Vec3) -> f64;
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3;
+}
+
+// Every direction equally likely
+pub(crate) struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        Vec3::random_unit_vector(sampler)
+    }
+}
+
+// Directions over the hemisphere around w, weighted by their cosine with it as for diffuse
+// reflection
+pub(crate) struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub(crate) fn new(w: Vec3) -> Self {
+        Self { uvw: Onb::new(w) }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine_theta = direction.unit_vector().dot(&self.uvw.w());
+        (cosine_theta / PI).max(0.0)
+    }
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        self.uvw.transform(Vec3::random_cosine_direction(sampler))
+    }
+}
+
+// The mirror direction pushed by fuzz times a random unit vector, as fuzzy metals reflect. The
+// pushed points lie uniformly on a sphere of radius fuzz around the tip of the unit vector
+// reflected, so a direction's density sums, over the points where it crosses that sphere, the
+// area density 1 / (4 pi fuzz^2) scaled by distance^2 / cosine to solid angle.
+pub(crate) struct FuzzyReflectionPdf {
+    reflected: Vec3,
+    fuzz: f64,
+}
+
+impl FuzzyReflectionPdf {
+    pub(crate) fn new(reflected: Vec3, fuzz: f64) -> Self {
+        Self {
+            reflected: reflected.unit_vector(),
+            fuzz,
+        }
+    }
+}
+
+impl Pdf for FuzzyReflectionPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let b = direction.unit_vector().dot(&self.reflected);
+        let discriminant = b * b - (1.0 - self.fuzz * self.fuzz);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let root = discriminant.sqrt();
+        // Along the direction the crossings are at distances b -/+ root, where the cosine with
+        // the sphere's normal is root / fuzz
+        let distances_squared: f64 = [b - root, b + root]
+            .iter()
+            .filter(|&&t| t > 0.0)
+            .map(|t| t * t)
+            .sum();
+        distances_squared / (4.0 * PI * self.fuzz * root)
+    }
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        self.reflected + self.fuzz * Vec3::random_unit_vector(sampler)
+    }
+}
+
+// Henyey-Greenstein scattering around the direction a ray was travelling, g being the mean
+// cosine of the scattering angle
+pub(crate) struct HenyeyGreensteinPdf {
+    uvw: Onb,
+    g: f64,
+}
+
+impl HenyeyGreensteinPdf {
+    pub(crate) fn new(forward: Vec3, g: f64) -> Self {
+        Self {
+            uvw: Onb::new(forward),
+            g,
+        }
+    }
+}
+
+impl Pdf for HenyeyGreensteinPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cos_theta = direction.unit_vector().dot(&self.uvw.w());
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+
+    // Inverts the distribution's CDF to draw the cosine of the scattering angle
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        let g = self.g;
+        let xi = sampler.random_f64();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * sampler.random_f64();
+        self.uvw.transform(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+// Directions from origin towards objects, usually the scene's lights
+pub(crate) struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub(crate) fn new(objects: &'a dyn Hittable, origin: Point3) -> Self {
+        Self { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        self.objects.random(&self.origin, sampler)
+    }
+}
+
+// An even mix of two densities, drawing from each half the time
+pub(crate) struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub(crate) fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
+        Self { p: [p0, p1] }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        if sampler.random_f64() < 0.5 {
+            self.p[0].generate(sampler)
+        } else {
+            self.p[1].generate(sampler)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::PI, sync::Arc};
+
+    use crate::{
+        color::Color,
+        material::DiffuseLight,
+        matrix::Matrix,
+        pdf::{
+            CosinePdf, FuzzyReflectionPdf, HenyeyGreensteinPdf, HittablePdf, MixturePdf, Pdf,
+            SpherePdf,
+        },
+        quad::Quad,
+        sampler::Sampler,
+        sphere::Sphere,
+        transform::{Transform, Transformed},
+        vec3::{Point3, Vec3},
+    };
+
+    // Estimates the integral of a density over all directions, which must be 1, and checks the
+    // directions it generates are ones it gives weight to
+    fn assert_normalized(pdf: &dyn Pdf) {
+        let mut sampler = Sampler::new(7);
+        let samples = 200_000;
+        let integral = (0..samples)
+            .map(|_| pdf.value(&Vec3::random_unit_vector(&mut sampler)) * 4.0 * PI)
+            .sum::<f64>()
+            / samples as f64;
+        assert!((integral - 1.0).abs() < 0.03, "integral {integral}");
+        for _ in 0..100 {
+            assert!(pdf.value(&pdf.generate(&mut sampler)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn densities_integrate_to_one() {
+        assert_normalized(&SpherePdf);
+        assert_normalized(&CosinePdf::new(Vec3::new(1.0, 2.0, -0.5)));
+        assert_normalized(&HenyeyGreensteinPdf::new(Vec3::new(0.0, 0.0, -1.0), 0.3));
+        assert_normalized(&FuzzyReflectionPdf::new(Vec3::new(1.0, 1.0, 0.0), 0.4));
+        assert_normalized(&FuzzyReflectionPdf::new(Vec3::new(0.0, 1.0, 0.0), 1.0));
+        assert_normalized(&MixturePdf::new(
+            &SpherePdf,
+            &CosinePdf::new(Vec3::new(0.0, 1.0, 0.0)),
+        ));
+    }
+
+    #[test]
+    fn lights_are_sampled_by_solid_angle() {
+        let lamp = Arc::new(DiffuseLight::new(Color::new(1.0, 1.0, 1.0)));
+        let origin = Point3::new(0.2, 0.1, 0.3);
+        let quad = Quad::new(
+            Point3::new(-0.5, 1.0, -0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            lamp.clone(),
+        );
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, lamp.clone());
+        assert_normalized(&HittablePdf::new(&quad, origin));
+        assert_normalized(&HittablePdf::new(&sphere, origin));
+
+        let matrix = Matrix::translation(Vec3::new(0.0, 2.0, 0.0))
+            * Matrix::rotation(Vec3::new(1.0, 0.0, 0.0), 30.0)
+            * Matrix::scaling(Vec3::new(2.0, 1.0, 0.5));
+        let unit = Arc::new(Sphere::new(Point3::origin(), 1.0, lamp));
+        let ellipsoid = Transformed::new(unit, Transform::new(matrix).unwrap());
+        assert_normalized(&HittablePdf::new(&ellipsoid, origin));
+    }
+}
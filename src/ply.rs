@@ -0,0 +1,524 @@
+// Stanford PLY import for ASCII and little/big-endian binary files. The vertex element provides
+// positions (x, y, z) and optionally normals (nx, ny, nz), colors (red, green, blue) and texture
+// coordinates (u, v or s, t); the face element provides vertex_indices lists, which are
+// triangulated as fans. Any other elements and properties are skipped. The whole file becomes a
+// single TriangleMesh with one material, accelerated by a MeshBvh.
+
+use std::{error::Error, fmt, fs, io, path::Path, sync::Arc};
+
+use crate::{
+    color::{srgb_to_linear, Color},
+    material::{Lambertian, Material},
+    mesh_bvh::MeshBvh,
+    triangle::{TriangleMesh, Uv},
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub(crate) enum PlyError {
+    Io(io::Error),
+    // The file is not valid PLY, or lacks the data a mesh needs
+    Format(String),
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::Io(e) => write!(f, "{e}"),
+            PlyError::Format(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl Error for PlyError {}
+
+impl From<io::Error> for PlyError {
+    fn from(e: io::Error) -> Self {
+        PlyError::Io(e)
+    }
+}
+
+fn format_error(message: impl Into<String>) -> PlyError {
+    PlyError::Format(message.into())
+}
+
+pub(crate) fn load_ply(path: &Path, mat: Option<Arc<dyn Material>>) -> Result<MeshBvh, PlyError> {
+    parse_ply(&fs::read(path)?, mat)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(Self::I8),
+            "uchar" | "uint8" => Some(Self::U8),
+            "short" | "int16" => Some(Self::I16),
+            "ushort" | "uint16" => Some(Self::U16),
+            "int" | "int32" => Some(Self::I32),
+            "uint" | "uint32" => Some(Self::U32),
+            "float" | "float32" => Some(Self::F32),
+            "double" | "float64" => Some(Self::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    // Scale that maps the type's full range onto [0, 1] for color components
+    fn color_scale(self) -> f64 {
+        match self {
+            Self::U8 | Self::I8 => 1.0 / 255.0,
+            Self::U16 | Self::I16 => 1.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PropertyKind {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|p| names.contains(&p.name.as_str()))
+    }
+}
+
+struct Header {
+    encoding: Encoding,
+    elements: Vec<Element>,
+}
+
+// Splits off and parses the header, returning it with the body that follows end_header
+fn parse_header(data: &[u8]) -> Result<(Header, &[u8]), PlyError> {
+    let mut rest = data;
+    let mut next_line = || {
+        let end = rest.iter().position(|&b| b == b'\n')?;
+        let line = &rest[..end];
+        rest = &rest[end + 1..];
+        Some(
+            String::from_utf8_lossy(line)
+                .trim_end_matches('\r')
+                .to_string(),
+        )
+    };
+
+    if next_line().as_deref() != Some("ply") {
+        return Err(format_error(
+            "not a PLY file, expected 'ply' on the first line",
+        ));
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut number = 1;
+    loop {
+        number += 1;
+        let line = next_line()
+            .ok_or_else(|| format_error("the header is not terminated by end_header"))?;
+        let error = |message: String| format_error(format!("header line {number}: {message}"));
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["end_header"] => break,
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", format, "1.0"] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::BinaryLittleEndian,
+                    "binary_big_endian" => Encoding::BinaryBigEndian,
+                    _ => return Err(error(format!("unknown format '{format}'"))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(format!("invalid element count '{count}'")))?,
+                properties: Vec::new(),
+            }),
+            ["property", rest @ ..] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error(String::from("property before any element")))?;
+                let scalar = |name: &str| {
+                    Scalar::parse(name).ok_or_else(|| error(format!("unknown type '{name}'")))
+                };
+                let (name, kind) = match rest {
+                    ["list", count, item, name] => (
+                        name,
+                        PropertyKind::List {
+                            count: scalar(count)?,
+                            item: scalar(item)?,
+                        },
+                    ),
+                    [ty, name] => (name, PropertyKind::Scalar(scalar(ty)?)),
+                    _ => return Err(error(format!("malformed property '{line}'"))),
+                };
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                });
+            }
+            _ => return Err(error(format!("unrecognised header line '{line}'"))),
+        }
+    }
+
+    let encoding = encoding.ok_or_else(|| format_error("the header has no format line"))?;
+    Ok((Header { encoding, elements }, rest))
+}
+
+// Reads successive values from the body in the file's encoding
+struct Body<'a> {
+    data: &'a [u8],
+    encoding: Encoding,
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: Scalar) -> Option<f64> {
+        match self.encoding {
+            Encoding::Ascii => {
+                let start = self.data.iter().position(|b| !b.is_ascii_whitespace())?;
+                let len = self.data[start..]
+                    .iter()
+                    .position(|b| b.is_ascii_whitespace())
+                    .unwrap_or(self.data.len() - start);
+                let token = std::str::from_utf8(&self.data[start..start + len]).ok()?;
+                self.data = &self.data[start + len..];
+                token.parse().ok()
+            }
+            Encoding::BinaryLittleEndian | Encoding::BinaryBigEndian => {
+                let size = ty.size();
+                if self.data.len() < size {
+                    return None;
+                }
+                let mut bytes = [0u8; 8];
+                bytes[..size].copy_from_slice(&self.data[..size]);
+                self.data = &self.data[size..];
+                if self.encoding == Encoding::BinaryBigEndian {
+                    bytes[..size].reverse();
+                }
+                let value = match ty {
+                    Scalar::I8 => bytes[0] as i8 as f64,
+                    Scalar::U8 => bytes[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes(bytes[..4].try_into().ok()?) as f64,
+                    Scalar::U32 => u32::from_le_bytes(bytes[..4].try_into().ok()?) as f64,
+                    Scalar::F32 => f32::from_le_bytes(bytes[..4].try_into().ok()?) as f64,
+                    Scalar::F64 => f64::from_le_bytes(bytes),
+                };
+                Some(value)
+            }
+        }
+    }
+}
+
+pub(crate) fn parse_ply(data: &[u8], mat: Option<Arc<dyn Material>>) -> Result<MeshBvh, PlyError> {
+    let (header, body) = parse_header(data)?;
+    let mut body = Body {
+        data: body,
+        encoding: header.encoding,
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut uvs: Vec<Uv> = Vec::new();
+    let mut faces: Vec<[u32; 3]> = Vec::new();
+    let mut values = Vec::new();
+    let mut face: Vec<u32> = Vec::new();
+
+    for element in &header.elements {
+        let xyz = [
+            element.find(&["x"]),
+            element.find(&["y"]),
+            element.find(&["z"]),
+        ];
+        let normal = [
+            element.find(&["nx"]),
+            element.find(&["ny"]),
+            element.find(&["nz"]),
+        ];
+        let rgb = [
+            element.find(&["red", "r", "diffuse_red"]),
+            element.find(&["green", "g", "diffuse_green"]),
+            element.find(&["blue", "b", "diffuse_blue"]),
+        ];
+        let uv = [
+            element.find(&["u", "s", "texture_u", "texture_s"]),
+            element.find(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let indices = element.find(&["vertex_indices", "vertex_index"]);
+
+        let is_vertex = element.name == "vertex";
+        if is_vertex && xyz.iter().any(Option::is_none) {
+            return Err(format_error(
+                "the vertex element needs x, y and z properties",
+            ));
+        }
+        let is_face = element.name == "face";
+        if is_face && indices.is_none() {
+            return Err(format_error("the face element needs a vertex_indices list"));
+        }
+
+        for item in 0..element.count {
+            let truncated =
+                || format_error(format!("{} {item}: unexpected end of data", element.name));
+            face.clear();
+            values.clear();
+            for (p, property) in element.properties.iter().enumerate() {
+                match property.kind {
+                    PropertyKind::Scalar(ty) => values.push(body.read(ty).ok_or_else(truncated)?),
+                    PropertyKind::List { count, item: ty } => {
+                        let n = body.read(count).ok_or_else(truncated)? as usize;
+                        values.push(n as f64);
+                        for _ in 0..n {
+                            let value = body.read(ty).ok_or_else(truncated)?;
+                            if is_face && Some(p) == indices {
+                                let index = u32::try_from(value as i64).map_err(|_| {
+                                    format_error(format!(
+                                        "face {item}: vertex index {value} is not a valid index"
+                                    ))
+                                })?;
+                                face.push(index);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if is_vertex {
+                let get = |i: Option<usize>| i.map(|i| values[i]);
+                let [x, y, z] = xyz.map(|i| get(i).unwrap_or_default());
+                positions.push(Point3::new(x, y, z));
+                if let [Some(nx), Some(ny), Some(nz)] = normal.map(get) {
+                    normals.push(Vec3::new(nx, ny, nz));
+                }
+                if let [Some(r), Some(g), Some(b)] = rgb.map(get) {
+                    let scale = |i: Option<usize>| match element.properties[i.unwrap()].kind {
+                        PropertyKind::Scalar(ty) => ty.color_scale(),
+                        PropertyKind::List { .. } => 1.0,
+                    };
+                    let (r, g, b) = (r * scale(rgb[0]), g * scale(rgb[1]), b * scale(rgb[2]));
+                    colors.push(Color::new(
+                        srgb_to_linear(r),
+                        srgb_to_linear(g),
+                        srgb_to_linear(b),
+                    ));
+                }
+                if let [Some(u), Some(v)] = uv.map(get) {
+                    uvs.push((u, v));
+                }
+            } else if is_face {
+                if face.len() < 3 {
+                    return Err(format_error(format!(
+                        "face {item}: needs at least 3 vertices, found {}",
+                        face.len()
+                    )));
+                }
+                for i in 1..face.len() - 1 {
+                    faces.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+        }
+    }
+
+    if let Some(&index) = faces
+        .iter()
+        .flatten()
+        .find(|&&i| i as usize >= positions.len())
+    {
+        return Err(format_error(format!(
+            "face vertex index {index} is out of range, there are {} vertices",
+            positions.len()
+        )));
+    }
+
+    let mat: Arc<dyn Material> = match mat {
+        Some(mat) => mat,
+        // Vertex colors modulate the albedo, so leave them untouched
+        None if !colors.is_empty() => Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))),
+        None => Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+    };
+    let mesh = TriangleMesh::new(positions, normals, uvs, colors, faces, mat);
+    Ok(MeshBvh::new(mesh))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        interval::Interval,
+        ply::{parse_ply, PlyError},
+        ray::Ray,
+        sampler::Sampler,
+        vec3::{Point3, Vec3},
+    };
+
+    // A unit square in the z = 0 plane as a single quad face, with red vertices
+    const SQUARE_ASCII: &str = "\
+ply
+format ascii 1.0
+comment made by hand
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 255 0 0
+0 1 0 255 0 0
+4 0 1 2 3
+0 1
+";
+
+    fn binary_square(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut data = format!(
+            "ply\nformat {format} 1.0\nelement vertex 4\nproperty double x\n\
+             property double y\nproperty double z\nproperty float nx\nproperty float ny\n\
+             property float nz\nelement face 2\nproperty list uchar uint vertex_indices\n\
+             end_header\n"
+        )
+        .into_bytes();
+        for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            for v in [x, y, 0.0f64] {
+                data.extend(if big_endian {
+                    v.to_be_bytes()
+                } else {
+                    v.to_le_bytes()
+                });
+            }
+            for n in [0.0, 0.0, 1.0f32] {
+                data.extend(if big_endian {
+                    n.to_be_bytes()
+                } else {
+                    n.to_le_bytes()
+                });
+            }
+        }
+        for face in [[0u32, 1, 2], [0, 2, 3]] {
+            data.push(3);
+            for i in face {
+                data.extend(if big_endian {
+                    i.to_be_bytes()
+                } else {
+                    i.to_le_bytes()
+                });
+            }
+        }
+        data
+    }
+
+    fn hit_square(mesh: &dyn Hittable) -> crate::hittable::HitRecord {
+        let mut sampler = Sampler::new(0);
+        let r = Ray::new(Point3::new(0.25, 0.75, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        mesh.hit(&r, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_ascii() {
+        let mesh = parse_ply(SQUARE_ASCII.as_bytes(), None).unwrap();
+        let rec = hit_square(&mesh);
+        assert_eq!(rec.t, 2.0);
+        assert_eq!(rec.vertex_color, Some(Color::new(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn parses_binary_in_both_byte_orders() {
+        for big_endian in [false, true] {
+            let mesh = parse_ply(&binary_square(big_endian), None).unwrap();
+            let rec = hit_square(&mesh);
+            assert_eq!(rec.t, 2.0);
+            assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+            assert_eq!(rec.vertex_color, None);
+        }
+    }
+
+    #[test]
+    fn reports_bad_files() {
+        let message = |data: &[u8]| match parse_ply(data, None) {
+            Err(PlyError::Format(message)) => message,
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("expected an error"),
+        };
+        assert_eq!(
+            message(b"solid cube\n"),
+            "not a PLY file, expected 'ply' on the first line"
+        );
+        assert_eq!(
+            message(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty quad x\nend_header\n"),
+            "header line 4: unknown type 'quad'"
+        );
+        let truncated = binary_square(false);
+        assert_eq!(
+            message(&truncated[..truncated.len() - 3]),
+            "face 1: unexpected end of data"
+        );
+        assert_eq!(
+            message(SQUARE_ASCII.replace("4 0 1 2 3", "3 0 1 9").as_bytes()),
+            "face vertex index 9 is out of range, there are 4 vertices"
+        );
+        assert_eq!(
+            message(SQUARE_ASCII.replace("4 0 1 2 3", "3 0 1 -2").as_bytes()),
+            "face 0: vertex index -2 is not a valid index"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+// Parallelogram spanned by the edge vectors u and v from the corner q
+pub(crate) struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    // Maps a point in the quad's plane to its (alpha, beta) coordinates along u and v
+    w: Vec3,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+    normal: Vec3,
+    d: f64,
+    area: f64,
+}
+
+impl Quad {
+    pub(crate) fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit_vector();
+        let d = normal.dot(&q);
+        let w = n / n.dot(&n);
+
+        // Compute the bounding box of all four vertices
+        let bbox_diagonal1 = Aabb::from_points(q, q + u + v);
+        let bbox_diagonal2 = Aabb::from_points(q + u, q + v);
+        Self {
+            q,
+            u,
+            v,
+            w,
+            mat,
+            bbox: Aabb::surrounding(&bbox_diagonal1, &bbox_diagonal2),
+            normal,
+            d,
+            area: n.length(),
+        }
+    }
+
+    // Where r meets the quad, for hit and for pdf_value, which has no sampler to pass along
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let denom = self.normal.dot(&r.direction());
+
+        // No hit if the ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        // Return None if the hit point parameter t is outside the ray interval
+        let t = (self.d - self.normal.dot(&r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        // Determine if the hit point lies within the planar shape using its plane coordinates
+        let intersection = r.at(t);
+        let planar_hitpt_vector = intersection - self.q;
+        let alpha = self.w.dot(&planar_hitpt_vector.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar_hitpt_vector));
+        let unit_interval = Interval::new(0.0, 1.0);
+        if unit_interval.clamp(alpha) != alpha || unit_interval.clamp(beta) != beta {
+            return None;
+        }
+
+        let mut rec = HitRecord::new();
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.set_face_normal(r, self.normal);
+        rec.mat = Some(self.mat.clone());
+        Some(rec)
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, ray_t: &Interval, _sampler: &mut Sampler) -> Option<HitRecord> {
+        self.intersect(r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // Points are chosen uniformly over the area, so the density per solid angle grows with the
+    // distance squared and with how obliquely the quad is seen
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let Some(rec) = self.intersect(
+            &Ray::new(*origin, *direction),
+            &Interval::new(0.001, f64::INFINITY),
+        ) else {
+            return 0.0;
+        };
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let p = self.q + (sampler.random_f64() * self.u) + (sampler.random_f64() * self.v);
+        p - *origin
+    }
+
+    fn sample_surface(&self, sampler: &mut Sampler) -> Option<(HitRecord, f64)> {
+        let (alpha, beta) = (sampler.random_f64(), sampler.random_f64());
+        let mut rec = HitRecord::new();
+        rec.p = self.q + alpha * self.u + beta * self.v;
+        rec.normal = self.normal;
+        rec.front_face = true;
+        (rec.u, rec.v) = (alpha, beta);
+        rec.mat = Some(self.mat.clone());
+        Some((rec, 1.0 / self.area))
+    }
+
+    // Points within a small tolerance of the quad, allowing for rounding in the hits that find
+    // them, count as on it
+    fn surface_pdf(&self, p: &Point3, _normal: &Vec3) -> f64 {
+        let tolerance = 1e-6 * (1.0 + self.d.abs());
+        if (self.normal.dot(p) - self.d).abs() > tolerance {
+            return 0.0;
+        }
+        let planar = *p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        let inside = Interval::new(-1e-6, 1.0 + 1e-6);
+        if inside.surrounds(alpha) && inside.surrounds(beta) {
+            1.0 / self.area
+        } else {
+            0.0
+        }
+    }
+}
+
+// Returns the six sides of the box with opposite corners a and b, facing outwards
+pub(crate) fn make_box(a: Point3, b: Point3, mat: Arc<dyn Material>) -> HittableList {
+    let mut sides = HittableList::empty();
+
+    let min = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+    let max = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+
+    let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+    let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+    let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+
+    // front
+    sides.add(Arc::new(Quad::new(
+        Point3::new(min.x(), min.y(), max.z()),
+        dx,
+        dy,
+        mat.clone(),
+    )));
+    // right
+    sides.add(Arc::new(Quad::new(
+        Point3::new(max.x(), min.y(), max.z()),
+        -dz,
+        dy,
+        mat.clone(),
+    )));
+    // back
+    sides.add(Arc::new(Quad::new(
+        Point3::new(max.x(), min.y(), min.z()),
+        -dx,
+        dy,
+        mat.clone(),
+    )));
+    // left
+    sides.add(Arc::new(Quad::new(
+        Point3::new(min.x(), min.y(), min.z()),
+        dz,
+        dy,
+        mat.clone(),
+    )));
+    // top
+    sides.add(Arc::new(Quad::new(
+        Point3::new(min.x(), max.y(), max.z()),
+        dx,
+        -dz,
+        mat.clone(),
+    )));
+    // bottom
+    sides.add(Arc::new(Quad::new(
+        Point3::new(min.x(), min.y(), min.z()),
+        dx,
+        dz,
+        mat,
+    )));
+
+    sides
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        interval::Interval,
+        material::Lambertian,
+        quad::{make_box, Quad},
+        ray::Ray,
+        sampler::Sampler,
+        vec3::{Point3, Vec3},
+    };
+
+    #[test]
+    fn quad_hit_reports_plane_coordinates() {
+        let mut sampler = Sampler::new(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            mat,
+        );
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+
+        let r = Ray::new(Point3::new(0.5, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = quad.hit(&r, &ray_t, &mut sampler).unwrap();
+        assert_eq!(rec.t, 2.0);
+        assert_eq!((rec.u, rec.v), (0.75, 0.25));
+        assert!(rec.front_face);
+
+        let miss = Ray::new(Point3::new(1.5, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.hit(&miss, &ray_t, &mut sampler).is_none());
+        let parallel = Ray::new(Point3::new(0.0, 0.0, 2.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(quad.hit(&parallel, &ray_t, &mut sampler).is_none());
+    }
+
+    #[test]
+    fn box_sides_face_outwards() {
+        let mut sampler = Sampler::new(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sides = make_box(
+            Point3::new(1.0, 1.0, 1.0),
+            Point3::new(-1.0, -1.0, -1.0),
+            mat,
+        );
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                let mut origin = Point3::origin();
+                origin[axis] = 3.0 * sign;
+                let rec = sides
+                    .hit(&Ray::new(origin, -origin), &ray_t, &mut sampler)
+                    .unwrap();
+                assert!(rec.front_face);
+                assert_eq!(rec.p[axis], sign);
+            }
+        }
+    }
+}
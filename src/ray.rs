@@ -3,11 +3,21 @@ use crate::vec3::{Point3, Vec3};
 pub(crate) struct Ray {
     origin: Point3,
     direction: Vec3,
+    // The moment within the frame the ray was cast, for scenes with moving objects
+    time: f64,
 }
 
 impl Ray {
     pub(crate) fn new(origin: Point3, direction: Vec3) -> Self {
-        Ray { origin, direction }
+        Self::with_time(origin, direction, 0.0)
+    }
+
+    pub(crate) fn with_time(origin: Point3, direction: Vec3, time: f64) -> Self {
+        Ray {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub(crate) fn origin(&self) -> Point3 {
@@ -18,6 +28,10 @@ impl Ray {
         self.direction
     }
 
+    pub(crate) fn time(&self) -> f64 {
+        self.time
+    }
+
     pub(crate) fn at(&self, t: f64) -> Point3 {
         self.origin + t * self.direction
     }
@@ -0,0 +1,62 @@
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+// Source of all randomness used while building and rendering a scene. Samplers are created from
+// a user-supplied seed, and per-pixel samplers are derived from that seed and the pixel
+// coordinates so that a render is reproducible no matter which thread draws which pixel.
+pub(crate) struct Sampler {
+    rng: SmallRng,
+}
+
+impl Sampler {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(splitmix64(seed)),
+        }
+    }
+
+    pub(crate) fn for_pixel(seed: u64, i: i64, j: i64) -> Self {
+        let pixel = splitmix64((j as u64) << 32 | (i as u64 & 0xffff_ffff));
+        Self::new(seed ^ pixel)
+    }
+
+    pub(crate) fn random_f64(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    pub(crate) fn random_f64_range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.random_f64()
+    }
+}
+
+// Scrambles nearby seeds (e.g. neighbouring pixel indices) into unrelated streams
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sampler::Sampler;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Sampler::new(42);
+        let mut b = Sampler::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.random_f64(), b.random_f64());
+        }
+    }
+
+    #[test]
+    fn pixel_samplers_are_independent() {
+        let mut a = Sampler::for_pixel(7, 10, 20);
+        let mut b = Sampler::for_pixel(7, 20, 10);
+        let mut c = Sampler::for_pixel(8, 10, 20);
+        let first = a.random_f64();
+        assert_ne!(first, b.random_f64());
+        assert_ne!(first, c.random_f64());
+        assert_eq!(first, Sampler::for_pixel(7, 10, 20).random_f64());
+    }
+}
@@ -0,0 +1,1226 @@
+// Loader for the plain text scene description format. A scene file is a list of directives, one
+// per line, each a keyword followed by whitespace separated key=value parameters:
+//
+//     # Comments run to the end of the line
+//     camera aspect_ratio=1.7778 image_width=400 lookfrom=13,2,3 lookat=0,0,0 vfov=20
+//     material ground lambertian albedo=0.5,0.5,0.5
+//     material glass dielectric refraction_index=1.5
+//     material steel metal albedo=0.7,0.6,0.5 fuzz=0.1
+//     material lamp diffuse_light emit=4,4,4
+//     texture tiles checker scale=0.5 even=0.2,0.3,0.1 odd=0.9,0.9,0.9
+//     material floor lambertian albedo=tiles
+//     texture earth image file=textures/earth.jpg wrap=repeat,clamp uv_scale=2,1
+//     texture veins marble scale=4 low=0.2,0.2,0.25 high=0.9
+//     material brushed metal albedo=0.8,0.8,0.8 fuzz=veins
+//     sphere center=0,-1000,0 radius=1000 material=ground
+//     quad q=-1,0,-1 u=2,0,0 v=0,0,2 material=lamp
+//     box min=0,0,0 max=1,2,1 material=steel
+//     triangle p0=0,0,0 p1=1,0,0 p2=0,1,0 material=red
+//     obj file=models/teapot.obj material=steel
+//     ply file=models/bunny.ply material=ground
+//     sphere center=0,0,0 radius=1 material=steel scale=2,1,1 rotate=0,45,0 translate=0,1,0
+//     object rock obj file=models/rock.obj scale=0.1
+//     instance rock translate=3,0,1 rotate=0,30,0
+//     sphere center=2,0.5,0 center_end=2,1,0 radius=0.5 material=ground
+//     instance rock translate=-3,0,1 rotate_end=0,90,0
+//     material smoke isotropic albedo=0.9,0.9,0.9
+//     medium box min=0,0,0 max=2,2,2 material=smoke density=0.5
+//     material cloud henyey_greenstein albedo=0.95,0.95,0.95 g=0.6
+//     volume file=models/cloud.vol material=cloud density=20
+//     light quad q=213,554,227 u=130,0,0 v=0,0,105 material=lamp
+//
+// Materials and textures are declared with a name before anything that refers to them. A
+// lambertian or metal albedo, and a checker's even and odd squares, may each be a texture name or
+// a constant color. Textures are "solid" with a color, "checker", alternating between its even and
+// odd textures in cubes of side scale, or "image", read from a PNG, JPEG, PPM or HDR file. An
+// image's filter is "bilinear" (the default) or "nearest", and its wrap mode "repeat" (the
+// default), "mirror" or "clamp", given once or separately for u,v. Its texture coordinates are
+// scaled by uv_scale, rotated counter-clockwise by uv_rotate degrees, then shifted by uv_offset.
+// Image and mesh files are found relative to the scene file. Every camera parameter is optional
+// and falls back to the same defaults as the book. The camera's background is "gradient" (the
+// default sky), "none" for black, or a color such as 0.1,0.1,0.1. Triangles may give per-vertex
+// normals n0, n1 and n2 for smooth shading. OBJ meshes use the materials from their own MTL
+// libraries unless a material is given; PLY meshes default to a grey diffuse material, tinted by
+// any vertex colors.
+//
+// Procedural textures blend from their low texture to their high one, black and white by default,
+// following a noise pattern of scale cycles per unit: "noise" is Perlin noise summed over octaves
+// (1 to 16, 1 by default), "turbulence" sums the magnitude of those octaves, "marble" bends bands
+// along z into veins, "wood" draws growth rings around the y axis and "worley" is cellular noise.
+// Low and high, like a metal's fuzz, may also be given as a single number for a grey. The noise is
+// drawn from the render seed.
+//
+// Any object may be moved with the optional scale (one number or three), rotate (degrees about
+// x, then y, then z) and translate parameters, applied in that order. "object NAME" followed by
+// an object directive defines a named object without adding it to the world; each "instance"
+// of it then places the same shared geometry, with its own transform.
+//
+// Objects can move during the frame for motion blur. Moving objects travel from where they are
+// at time 0 to where they are at time 1: spheres linearly to center_end, and any object to the
+// placement given by translate_end, rotate_end and scale_end (each defaulting to its start).
+// The camera's shutter_open and shutter_close, 0 and 1 by default, bound the times rays see.
+//
+// "medium" followed by an object directive fills that object with a participating medium, such
+// as fog or smoke, of the given density. Rays scatter inside it with the object's material as
+// the phase function, normally an "isotropic" material with an albedo. The object must be convex.
+// A "volume" instead reads a medium whose density varies from a voxel grid in a Mitsuba VOL file,
+// multiplied by density. Its material is the phase function too: "isotropic", or
+// "henyey_greenstein" with an anisotropy g from -1 (scattering backwards) to 1 (forwards).
+//
+// "light" followed by a sphere, quad or box directive adds that object as usual and also marks it
+// as a light, which diffuse and fuzzy surfaces and media then sample directly instead of waiting
+// for rays to find it by chance. Lights should be emissive and cannot move.
+
+use std::{collections::HashMap, error::Error, fmt, fs, io, path::Path, sync::Arc};
+
+use crate::{
+    bvh::{BvhNode, SplitMethod},
+    camera::{Background, Camera},
+    color::Color,
+    constant_medium::ConstantMedium,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    material::{
+        Dielectric, DiffuseLight, HenyeyGreenstein, Isotropic, Lambertian, Material, Metal,
+    },
+    obj::load_obj,
+    ply::load_ply,
+    quad::{make_box, Quad},
+    sampler::Sampler,
+    sphere::Sphere,
+    texture::{
+        CheckerTexture, Filter, ImageTexture, MarbleTexture, NoiseTexture, SolidColor, Texture,
+        UvTransform, WoodTexture, WorleyTexture, WrapMode,
+    },
+    transform::{MovingTransformed, Placement, Transformed},
+    triangle::{Triangle, DEFAULT_UVS},
+    vec3::{Point3, Vec3},
+    volume::{load_vol, GridMedium},
+};
+
+pub(crate) struct Scene {
+    pub(crate) camera: Camera,
+    pub(crate) world: HittableList,
+    // Emitters the renderer aims rays at directly; each is in the world too
+    pub(crate) lights: HittableList,
+}
+
+#[derive(Debug)]
+pub(crate) enum SceneError {
+    Io(io::Error),
+    // A problem with the contents of the file, reported against its 1-based line number
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{e}"),
+            SceneError::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+pub(crate) fn load_scene(path: &Path, sampler: &mut Sampler) -> Result<Scene, SceneError> {
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    parse_scene(&fs::read_to_string(path)?, base_dir, sampler)
+}
+
+// Parses a scene, resolving any files it references relative to base_dir
+// Procedural textures draw their random tables from sampler
+pub(crate) fn parse_scene(
+    source: &str,
+    base_dir: &Path,
+    sampler: &mut Sampler,
+) -> Result<Scene, SceneError> {
+    let mut camera: Option<Camera> = None;
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut textures: HashMap<String, Arc<dyn Texture>> = HashMap::new();
+    let mut objects: HashMap<String, Arc<dyn Hittable>> = HashMap::new();
+    let mut world = HittableList::empty();
+    let mut lights = HittableList::empty();
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let text = text.split('#').next().unwrap_or_default();
+        let mut tokens = text.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        match keyword {
+            "camera" => {
+                if camera.is_some() {
+                    return Err(parse_error(line, "the camera is already defined"));
+                }
+                camera = Some(parse_camera(Params::parse(line, tokens)?)?);
+            }
+            "material" => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| parse_error(line, "expected a material name"))?;
+                let kind = tokens
+                    .next()
+                    .ok_or_else(|| parse_error(line, "expected a material type"))?;
+                if materials.contains_key(name) {
+                    return Err(parse_error(
+                        line,
+                        format!("material '{name}' is already defined"),
+                    ));
+                }
+                let mat = parse_material(kind, Params::parse(line, tokens)?, &textures)?;
+                materials.insert(name.to_string(), mat);
+            }
+            "texture" => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| parse_error(line, "expected a texture name"))?;
+                let kind = tokens
+                    .next()
+                    .ok_or_else(|| parse_error(line, "expected a texture type"))?;
+                if textures.contains_key(name) {
+                    return Err(parse_error(
+                        line,
+                        format!("texture '{name}' is already defined"),
+                    ));
+                }
+                let params = Params::parse(line, tokens)?;
+                let texture = parse_texture(kind, params, &textures, base_dir, sampler)?;
+                textures.insert(name.to_string(), texture);
+            }
+            "object" => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| parse_error(line, "expected an object name"))?;
+                let kind = tokens
+                    .next()
+                    .ok_or_else(|| parse_error(line, "expected an object type"))?;
+                if objects.contains_key(name) {
+                    return Err(parse_error(
+                        line,
+                        format!("object '{name}' is already defined"),
+                    ));
+                }
+                let mut params = Params::parse(line, tokens)?;
+                let mut parts = parse_geometry(kind, &mut params, &materials, base_dir, None)?;
+                let placement = params.placement()?;
+                params.finish()?;
+                // Instances share one object, so gather multi-part meshes under a BVH
+                let object: Arc<dyn Hittable> = match parts.len() {
+                    1 => parts.remove(0),
+                    _ => {
+                        let mut list = HittableList::empty();
+                        for part in parts {
+                            list.add(part);
+                        }
+                        Arc::new(BvhNode::new(list, SplitMethod::Sah))
+                    }
+                };
+                objects.insert(name.to_string(), place(object, placement, line)?);
+            }
+            "medium" => {
+                let kind = tokens
+                    .next()
+                    .ok_or_else(|| parse_error(line, "expected an object type"))?;
+                let mut params = Params::parse(line, tokens)?;
+                let density = params.require_f64("density")?;
+                if density <= 0.0 {
+                    return Err(parse_error(line, "'density' must be positive"));
+                }
+                // The boundary's material is the medium's phase function, so it is required even
+                // for meshes
+                let phase = params.require_material("material", &materials)?;
+                let parts = parse_geometry(kind, &mut params, &materials, base_dir, Some(&phase))?;
+                let placement = params.placement()?;
+                params.finish()?;
+                for part in parts {
+                    let boundary = place(part, placement, line)?;
+                    world.add(Arc::new(ConstantMedium::new(
+                        boundary,
+                        density,
+                        phase.clone(),
+                    )));
+                }
+            }
+            "light" => {
+                let kind = tokens
+                    .next()
+                    .ok_or_else(|| parse_error(line, "expected an object type"))?;
+                if !matches!(kind, "sphere" | "quad" | "box") {
+                    return Err(parse_error(
+                        line,
+                        format!("'{kind}' cannot be a light, expected sphere, quad or box"),
+                    ));
+                }
+                let mut params = Params::parse(line, tokens)?;
+                if params.values.contains_key("center_end") {
+                    return Err(parse_error(line, "lights cannot move"));
+                }
+                let parts = parse_geometry(kind, &mut params, &materials, base_dir, None)?;
+                let placement = params.placement()?;
+                params.finish()?;
+                if placement.0 != placement.1 {
+                    return Err(parse_error(line, "lights cannot move"));
+                }
+                for part in parts {
+                    let light = place(part, placement, line)?;
+                    world.add(light.clone());
+                    lights.add(light);
+                }
+            }
+            "instance" => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| parse_error(line, "expected an object name"))?;
+                let object = objects
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| parse_error(line, format!("unknown object '{name}'")))?;
+                let mut params = Params::parse(line, tokens)?;
+                let placement = params.placement()?;
+                params.finish()?;
+                world.add(place(object, placement, line)?);
+            }
+            _ => {
+                let mut params = Params::parse(line, tokens)?;
+                let parts = parse_geometry(keyword, &mut params, &materials, base_dir, None)?;
+                let placement = params.placement()?;
+                params.finish()?;
+                for part in parts {
+                    world.add(place(part, placement, line)?);
+                }
+            }
+        }
+    }
+    let camera = match camera {
+        Some(camera) => camera,
+        None => parse_camera(Params::default())?,
+    };
+    Ok(Scene {
+        camera,
+        world,
+        lights,
+    })
+}
+
+// Puts an object at its placement, moving it over the frame when the two ends differ
+fn place(
+    object: Arc<dyn Hittable>,
+    (start, end): (Placement, Placement),
+    line: usize,
+) -> Result<Arc<dyn Hittable>, SceneError> {
+    let singular = || parse_error(line, "'scale' must not be zero along any axis");
+    if start != end {
+        let moving = MovingTransformed::new(object, start, end).ok_or_else(singular)?;
+        return Ok(Arc::new(moving));
+    }
+    if start == Placement::identity() {
+        return Ok(object);
+    }
+    let transform = start.transform().ok_or_else(singular)?;
+    Ok(Arc::new(Transformed::new(object, transform)))
+}
+
+// Builds the objects for a geometry directive; OBJ files produce one part per group.
+// Media pass their phase function as boundary, having read the material parameter themselves.
+// A boundary's surface is never shaded, so shapes just carry it and meshes keep their own.
+fn parse_geometry(
+    keyword: &str,
+    params: &mut Params,
+    materials: &HashMap<String, Arc<dyn Material>>,
+    base_dir: &Path,
+    boundary: Option<&Arc<dyn Material>>,
+) -> Result<Vec<Arc<dyn Hittable>>, SceneError> {
+    let material = |params: &mut Params| match boundary {
+        Some(mat) => Ok(mat.clone()),
+        None => params.require_material("material", materials),
+    };
+    let mut parts: Vec<Arc<dyn Hittable>> = Vec::new();
+    match keyword {
+        "sphere" => {
+            let center = params.require_vec3("center")?;
+            let center_end = params.vec3_or("center_end", center)?;
+            let radius = params.require_f64("radius")?;
+            let mat = material(params)?;
+            let sphere = if center_end == center {
+                Sphere::new(center, radius, mat)
+            } else {
+                Sphere::moving(center, center_end, radius, mat)
+            };
+            parts.push(Arc::new(sphere));
+        }
+        "quad" => {
+            let q = params.require_vec3("q")?;
+            let u = params.require_vec3("u")?;
+            let v = params.require_vec3("v")?;
+            let mat = material(params)?;
+            parts.push(Arc::new(Quad::new(q, u, v, mat)));
+        }
+        "box" => {
+            let min = params.require_vec3("min")?;
+            let max = params.require_vec3("max")?;
+            let mat = material(params)?;
+            parts.push(Arc::new(make_box(min, max, mat)));
+        }
+        "triangle" => {
+            let positions = [
+                params.require_vec3("p0")?,
+                params.require_vec3("p1")?,
+                params.require_vec3("p2")?,
+            ];
+            let normals = match (params.take("n0"), params.take("n1"), params.take("n2")) {
+                (None, None, None) => None,
+                (Some(n0), Some(n1), Some(n2)) => Some([
+                    params.parse_vec3("n0", n0)?,
+                    params.parse_vec3("n1", n1)?,
+                    params.parse_vec3("n2", n2)?,
+                ]),
+                _ => {
+                    return Err(parse_error(
+                        params.line,
+                        "give all of n0, n1 and n2 or none",
+                    ))
+                }
+            };
+            let mat = material(params)?;
+            let triangle = match normals {
+                Some(normals) => {
+                    Triangle::with_attributes(positions, Some(normals), DEFAULT_UVS, mat)
+                }
+                None => Triangle::new(positions, mat),
+            };
+            parts.push(Arc::new(triangle));
+        }
+        "obj" => {
+            let file = params.take("file").ok_or_else(|| params.missing("file"))?;
+            let mat = match boundary {
+                Some(_) => None,
+                None => params.optional_material("material", materials)?,
+            };
+            let mesh = load_obj(&base_dir.join(file), mat)
+                .map_err(|e| parse_error(params.line, format!("failed to load mesh: {e}")))?;
+            // Keep each group's mesh separate so the BVH can split them apart
+            parts.extend(mesh.into_objects());
+        }
+        "volume" => {
+            let file = params.take("file").ok_or_else(|| params.missing("file"))?;
+            let phase = material(params)?;
+            let density = params.f64_or("density", 1.0)?;
+            let path = base_dir.join(file);
+            let grid = load_vol(&path).map_err(|e| {
+                parse_error(
+                    params.line,
+                    format!("failed to load volume: {}: {e}", path.display()),
+                )
+            })?;
+            parts.push(Arc::new(GridMedium::new(grid, density, phase)));
+        }
+        "ply" => {
+            let file = params.take("file").ok_or_else(|| params.missing("file"))?;
+            let mat = match boundary {
+                Some(_) => None,
+                None => params.optional_material("material", materials)?,
+            };
+            let path = base_dir.join(file);
+            let mesh = load_ply(&path, mat).map_err(|e| {
+                parse_error(
+                    params.line,
+                    format!("failed to load mesh: {}: {e}", path.display()),
+                )
+            })?;
+            parts.push(Arc::new(mesh));
+        }
+        _ => {
+            return Err(parse_error(
+                params.line,
+                format!("unknown directive '{keyword}'"),
+            ))
+        }
+    }
+    Ok(parts)
+}
+
+fn parse_camera(mut params: Params) -> Result<Camera, SceneError> {
+    let mut camera = Camera::new(
+        params.positive_f64_or("aspect_ratio", 1.0)?,
+        params.positive_i64_or("image_width", 100)?,
+        params.positive_i64_or("samples_per_pixel", 10)?,
+        params.positive_i64_or("max_depth", 10)?,
+        params.vec3_or("lookfrom", Point3::origin())?,
+        params.vec3_or("lookat", Point3::new(0.0, 0.0, -1.0))?,
+        params.vec3_or("vup", Vec3::new(0.0, 1.0, 0.0))?,
+        params.f64_between_or("vfov", 90.0, (0.0, 180.0))?,
+        params.f64_or("defocus_angle", 0.0)?,
+        params.positive_f64_or("focus_dist", 10.0)?,
+    );
+    let background = match params.take("background") {
+        None | Some("gradient") => Background::Gradient,
+        Some("none") => Background::None,
+        Some(value) => Background::Solid(params.parse_vec3("background", value)?),
+    };
+    camera.set_background(background);
+    let shutter_open = params.f64_or("shutter_open", 0.0)?;
+    let shutter_close = params.f64_or("shutter_close", 1.0)?;
+    if shutter_open > shutter_close {
+        return Err(parse_error(
+            params.line,
+            "'shutter_close' must not be earlier than 'shutter_open'",
+        ));
+    }
+    camera.set_shutter(shutter_open, shutter_close);
+    params.finish()?;
+    Ok(camera)
+}
+
+fn parse_material(
+    kind: &str,
+    mut params: Params,
+    textures: &HashMap<String, Arc<dyn Texture>>,
+) -> Result<Arc<dyn Material>, SceneError> {
+    let mat: Arc<dyn Material> = match kind {
+        "lambertian" => Arc::new(Lambertian::from_texture(
+            params.require_texture("albedo", textures)?,
+        )),
+        "metal" => Arc::new(Metal::from_texture(
+            params.require_texture("albedo", textures)?,
+            params.texture_or("fuzz", 0.0, textures)?,
+        )),
+        "dielectric" => Arc::new(Dielectric::new(params.require_f64("refraction_index")?)),
+        "diffuse_light" => Arc::new(DiffuseLight::new(params.require_vec3("emit")?)),
+        "isotropic" => Arc::new(Isotropic::from_texture(
+            params.require_texture("albedo", textures)?,
+        )),
+        "henyey_greenstein" => Arc::new(HenyeyGreenstein::from_texture(
+            params.require_texture("albedo", textures)?,
+            params.f64_or("g", 0.0)?,
+        )),
+        _ => {
+            return Err(parse_error(
+                params.line,
+                format!("unknown material type '{kind}'"),
+            ))
+        }
+    };
+    params.finish()?;
+    Ok(mat)
+}
+
+fn parse_texture(
+    kind: &str,
+    mut params: Params,
+    textures: &HashMap<String, Arc<dyn Texture>>,
+    base_dir: &Path,
+    sampler: &mut Sampler,
+) -> Result<Arc<dyn Texture>, SceneError> {
+    let texture: Arc<dyn Texture> = match kind {
+        "solid" => Arc::new(SolidColor::new(params.require_vec3("color")?)),
+        "checker" => Arc::new(CheckerTexture::new(
+            params.f64_or("scale", 1.0)?,
+            params.require_texture("even", textures)?,
+            params.require_texture("odd", textures)?,
+        )),
+        "image" => {
+            let file = params.take("file").ok_or_else(|| params.missing("file"))?;
+            let path = base_dir.join(file);
+            let mut image = ImageTexture::load(&path).map_err(|e| {
+                parse_error(
+                    params.line,
+                    format!("failed to load image: {}: {e}", path.display()),
+                )
+            })?;
+            let (wrap_u, wrap_v) = params.wrap_or("wrap", WrapMode::Repeat)?;
+            image.set_wrap(wrap_u, wrap_v);
+            image.set_filter(match params.take("filter") {
+                None | Some("bilinear") => Filter::Bilinear,
+                Some("nearest") => Filter::Nearest,
+                Some(value) => return Err(params.invalid("filter", value, "nearest or bilinear")),
+            });
+            image.set_transform(UvTransform::from_parts(
+                params.pair_or("uv_scale", (1.0, 1.0))?,
+                params.f64_or("uv_rotate", 0.0)?,
+                params.pair_or("uv_offset", (0.0, 0.0))?,
+            ));
+            Arc::new(image)
+        }
+        "noise" | "turbulence" => {
+            let scale = params.f64_or("scale", 1.0)?;
+            // Each octave doubles the frequency, so many more would overflow the lattice
+            let octaves = match params.take("octaves") {
+                Some(value) => match value.parse::<usize>() {
+                    Ok(n) if (1..=16).contains(&n) => n,
+                    _ => return Err(params.invalid("octaves", value, "an integer from 1 to 16")),
+                },
+                None => 1,
+            };
+            let low = params.texture_or("low", 0.0, textures)?;
+            let high = params.texture_or("high", 1.0, textures)?;
+            let mut noise = NoiseTexture::new(sampler, scale, octaves, low, high);
+            noise.set_turbulent(kind == "turbulence");
+            Arc::new(noise)
+        }
+        "marble" | "wood" | "worley" => {
+            let scale = params.f64_or("scale", 1.0)?;
+            let low = params.texture_or("low", 0.0, textures)?;
+            let high = params.texture_or("high", 1.0, textures)?;
+            match kind {
+                "marble" => Arc::new(MarbleTexture::new(sampler, scale, low, high)),
+                "wood" => Arc::new(WoodTexture::new(sampler, scale, low, high)),
+                _ => Arc::new(WorleyTexture::new(sampler, scale, low, high)),
+            }
+        }
+        _ => {
+            return Err(parse_error(
+                params.line,
+                format!("unknown texture type '{kind}'"),
+            ))
+        }
+    };
+    params.finish()?;
+    Ok(texture)
+}
+
+fn parse_error(line: usize, message: impl Into<String>) -> SceneError {
+    SceneError::Parse {
+        line,
+        message: message.into(),
+    }
+}
+
+// The key=value parameters of a single directive. Parameters are removed as they are read so
+// that finish() can reject any the directive does not understand.
+#[derive(Default)]
+struct Params<'a> {
+    line: usize,
+    values: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Params<'a> {
+    fn parse(line: usize, tokens: impl Iterator<Item = &'a str>) -> Result<Self, SceneError> {
+        let mut values = HashMap::new();
+        for token in tokens {
+            let Some((key, value)) = token.split_once('=') else {
+                return Err(parse_error(
+                    line,
+                    format!("expected key=value, found '{token}'"),
+                ));
+            };
+            if values.insert(key, value).is_some() {
+                return Err(parse_error(
+                    line,
+                    format!("'{key}' is given more than once"),
+                ));
+            }
+        }
+        Ok(Self { line, values })
+    }
+
+    fn take(&mut self, key: &str) -> Option<&'a str> {
+        self.values.remove(key)
+    }
+
+    fn missing(&self, key: &str) -> SceneError {
+        parse_error(self.line, format!("missing required parameter '{key}'"))
+    }
+
+    fn invalid(&self, key: &str, value: &str, expected: &str) -> SceneError {
+        parse_error(
+            self.line,
+            format!("'{key}' expects {expected}, found '{value}'"),
+        )
+    }
+
+    fn f64_or(&mut self, key: &str, default: f64) -> Result<f64, SceneError> {
+        match self.take(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| self.invalid(key, value, "a number")),
+            None => Ok(default),
+        }
+    }
+
+    fn require_f64(&mut self, key: &str) -> Result<f64, SceneError> {
+        if !self.values.contains_key(key) {
+            return Err(self.missing(key));
+        }
+        self.f64_or(key, 0.0)
+    }
+
+    fn positive_f64_or(&mut self, key: &str, default: f64) -> Result<f64, SceneError> {
+        match self.take(key) {
+            Some(value) => match value.parse::<f64>() {
+                Ok(x) if x.is_finite() && x > 0.0 => Ok(x),
+                _ => Err(self.invalid(key, value, "a positive number")),
+            },
+            None => Ok(default),
+        }
+    }
+
+    // A number strictly between min and max
+    fn f64_between_or(
+        &mut self,
+        key: &str,
+        default: f64,
+        (min, max): (f64, f64),
+    ) -> Result<f64, SceneError> {
+        match self.take(key) {
+            Some(value) => match value.parse::<f64>() {
+                Ok(x) if x > min && x < max => Ok(x),
+                _ => Err(self.invalid(key, value, &format!("a number between {min} and {max}"))),
+            },
+            None => Ok(default),
+        }
+    }
+
+    fn positive_i64_or(&mut self, key: &str, default: i64) -> Result<i64, SceneError> {
+        match self.take(key) {
+            Some(value) => match value.parse::<i64>() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => Err(self.invalid(key, value, "a positive integer")),
+            },
+            None => Ok(default),
+        }
+    }
+
+    fn vec3_or(&mut self, key: &str, default: Vec3) -> Result<Vec3, SceneError> {
+        match self.take(key) {
+            Some(value) => self.parse_vec3(key, value),
+            None => Ok(default),
+        }
+    }
+
+    fn parse_vec3(&self, key: &str, value: &str) -> Result<Vec3, SceneError> {
+        let components: Vec<f64> = value
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| self.invalid(key, value, "three comma separated numbers"))?;
+        match components[..] {
+            [x, y, z] => Ok(Vec3::new(x, y, z)),
+            _ => Err(self.invalid(key, value, "three comma separated numbers")),
+        }
+    }
+
+    fn require_vec3(&mut self, key: &str) -> Result<Vec3, SceneError> {
+        if !self.values.contains_key(key) {
+            return Err(self.missing(key));
+        }
+        self.vec3_or(key, Vec3::origin())
+    }
+
+    fn pair_or(&mut self, key: &str, default: (f64, f64)) -> Result<(f64, f64), SceneError> {
+        let Some(value) = self.take(key) else {
+            return Ok(default);
+        };
+        let components: Vec<f64> = value
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| self.invalid(key, value, "two comma separated numbers"))?;
+        match components[..] {
+            [a, b] => Ok((a, b)),
+            _ => Err(self.invalid(key, value, "two comma separated numbers")),
+        }
+    }
+
+    // Texture wrap modes for u and v, given as one mode for both or as two comma separated
+    fn wrap_or(
+        &mut self,
+        key: &str,
+        default: WrapMode,
+    ) -> Result<(WrapMode, WrapMode), SceneError> {
+        let Some(value) = self.take(key) else {
+            return Ok((default, default));
+        };
+        let modes = value
+            .split(',')
+            .map(|mode| match mode {
+                "repeat" => Some(WrapMode::Repeat),
+                "mirror" => Some(WrapMode::Mirror),
+                "clamp" => Some(WrapMode::Clamp),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        match modes.as_deref() {
+            Some(&[mode]) => Ok((mode, mode)),
+            Some(&[u, v]) => Ok((u, v)),
+            _ => Err(self.invalid(key, value, "repeat, mirror or clamp, once or for u,v")),
+        }
+    }
+
+    // The optional scale, rotate and translate parameters of an object, plus their _end
+    // counterparts for an object that moves over the frame
+    fn placement(&mut self) -> Result<(Placement, Placement), SceneError> {
+        let start = Placement {
+            translate: self.vec3_or("translate", Vec3::origin())?,
+            rotate: self.vec3_or("rotate", Vec3::origin())?,
+            scale: self.scale_or("scale", Vec3::new(1.0, 1.0, 1.0))?,
+        };
+        let end = Placement {
+            translate: self.vec3_or("translate_end", start.translate)?,
+            rotate: self.vec3_or("rotate_end", start.rotate)?,
+            scale: self.scale_or("scale_end", start.scale)?,
+        };
+        Ok((start, end))
+    }
+
+    // A scale factor given either as one number for all axes or as three
+    fn scale_or(&mut self, key: &str, default: Vec3) -> Result<Vec3, SceneError> {
+        match self.take(key) {
+            Some(value) if value.contains(',') => self.parse_vec3(key, value),
+            Some(value) => {
+                let s = value
+                    .parse()
+                    .map_err(|_| self.invalid(key, value, "a number or three numbers"))?;
+                Ok(Vec3::new(s, s, s))
+            }
+            None => Ok(default),
+        }
+    }
+
+    // A texture given either by name or as a constant color such as 0.8,0.2,0.2
+    fn require_texture(
+        &mut self,
+        key: &str,
+        textures: &HashMap<String, Arc<dyn Texture>>,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        let value = self.take(key).ok_or_else(|| self.missing(key))?;
+        if value.contains(',') {
+            return Ok(Arc::new(SolidColor::new(self.parse_vec3(key, value)?)));
+        }
+        textures
+            .get(value)
+            .cloned()
+            .ok_or_else(|| parse_error(self.line, format!("unknown texture '{value}'")))
+    }
+
+    // Like require_texture, also accepting one number for a grey
+    fn texture_or(
+        &mut self,
+        key: &str,
+        default: f64,
+        textures: &HashMap<String, Arc<dyn Texture>>,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        let grey = |value: f64| Color::new(value, value, value);
+        match self.values.get(key).map(|value| value.parse()) {
+            Some(Err(_)) => self.require_texture(key, textures),
+            Some(Ok(value)) => {
+                self.take(key);
+                Ok(Arc::new(SolidColor::new(grey(value))))
+            }
+            None => Ok(Arc::new(SolidColor::new(grey(default)))),
+        }
+    }
+
+    fn require_material(
+        &mut self,
+        key: &str,
+        materials: &HashMap<String, Arc<dyn Material>>,
+    ) -> Result<Arc<dyn Material>, SceneError> {
+        let name = self.take(key).ok_or_else(|| self.missing(key))?;
+        materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| parse_error(self.line, format!("unknown material '{name}'")))
+    }
+
+    fn optional_material(
+        &mut self,
+        key: &str,
+        materials: &HashMap<String, Arc<dyn Material>>,
+    ) -> Result<Option<Arc<dyn Material>>, SceneError> {
+        self.values
+            .contains_key(key)
+            .then(|| self.require_material(key, materials))
+            .transpose()
+    }
+
+    fn finish(self) -> Result<(), SceneError> {
+        let mut unknown: Vec<&str> = self.values.into_keys().collect();
+        unknown.sort_unstable();
+        match unknown.first() {
+            Some(key) => Err(parse_error(self.line, format!("unknown parameter '{key}'"))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        interval::Interval,
+        material::ScatterRecord,
+        ray::Ray,
+        sampler::Sampler,
+        scene::{load_scene, parse_scene, SceneError},
+        vec3::{Point3, Vec3},
+    };
+
+    fn error_of(source: &str) -> (usize, String) {
+        match parse_scene(source, Path::new("."), &mut Sampler::new(0)) {
+            Err(SceneError::Parse { line, message }) => (line, message),
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn parses_materials_and_spheres() {
+        let mut sampler = Sampler::new(0);
+        let scene = parse_scene(
+            "# two spheres\n\
+             camera image_width=40 aspect_ratio=2 lookfrom=0,0,5 lookat=0,0,0\n\
+             \n\
+             material red lambertian albedo=0.8,0.1,0.1  # trailing comment\n\
+             material glass dielectric refraction_index=1.5\n\
+             sphere center=0,0,0 radius=1 material=red\n\
+             sphere center=0,0,-3 radius=0.5 material=glass\n\
+             triangle p0=5,5,5 p1=6,5,5 p2=5,6,5 n0=0,0,1 n1=0,0,1 n2=0,1,1 material=red\n",
+            Path::new("."),
+            &mut Sampler::new(0),
+        )
+        .unwrap();
+
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = scene
+            .world
+            .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .unwrap();
+        assert_eq!(rec.t, 4.0);
+        assert_eq!(scene.world.bounding_box().z.min, -3.5);
+    }
+
+    #[test]
+    fn example_scenes_load() {
+        let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for file in [
+            "three_spheres.scene",
+            "cornell_box.scene",
+            "pyramid.scene",
+            "octahedron.scene",
+            "instances.scene",
+            "motion_blur.scene",
+            "checkered.scene",
+            "textured.scene",
+            "procedural.scene",
+            "fog.scene",
+            "cloud.scene",
+        ] {
+            let scene = load_scene(&scenes.join(file), &mut Sampler::new(0)).unwrap();
+            assert!(!scene.world.bounding_box().is_empty());
+        }
+    }
+
+    #[test]
+    fn transforms_and_instances_objects() {
+        let mut sampler = Sampler::new(0);
+        let scene = parse_scene(
+            "material red lambertian albedo=0.8,0.1,0.1
+             box min=-1,-1,-1 max=1,1,1 material=red scale=0.5,1,1 translate=0,0,-4
+             object ball sphere center=0,0,0 radius=1 material=red scale=0.5
+             instance ball translate=3,0,0
+             instance ball translate=-3,0,0 scale=2
+",
+            Path::new("."),
+            &mut Sampler::new(0),
+        )
+        .unwrap();
+
+        let mut hit = |origin: Point3| {
+            let r = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0));
+            scene
+                .world
+                .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+                .map(|rec| rec.t)
+        };
+        assert_eq!(hit(Point3::new(0.0, 0.0, 0.0)), Some(3.0));
+        // The box was squashed to half its width
+        assert_eq!(hit(Point3::new(0.75, 0.0, 0.0)), None);
+        assert_eq!(hit(Point3::new(3.0, 0.0, 5.0)), Some(4.5));
+        assert_eq!(hit(Point3::new(-3.0, 0.0, 5.0)), Some(4.0));
+        assert!((scene.world.bounding_box().x.min + 4.0).abs() < 1e-3);
+
+        assert_eq!(
+            error_of(
+                "material m metal albedo=1,1,1
+sphere center=0,0,0 radius=1 material=m scale=1,0,1"
+            ),
+            (2, String::from("'scale' must not be zero along any axis"))
+        );
+        assert_eq!(
+            error_of("material m isotropic albedo=1,1,1\nmedium sphere center=0,0,0 radius=1 material=m density=0"),
+            (2, String::from("'density' must be positive"))
+        );
+        assert_eq!(
+            error_of("medium obj file=models/pyramid.obj density=1"),
+            (1, String::from("missing required parameter 'material'"))
+        );
+        assert_eq!(
+            error_of("instance rock"),
+            (1, String::from("unknown object 'rock'"))
+        );
+    }
+
+    #[test]
+    fn moves_objects_over_the_frame() {
+        let mut sampler = Sampler::new(0);
+        let scene = parse_scene(
+            "camera shutter_open=0.25 shutter_close=0.75\n\
+             material red lambertian albedo=0.8,0.1,0.1\n\
+             sphere center=0,0,-2 center_end=0,0,-4 radius=1 material=red\n\
+             box min=-1,-1,-1 max=1,1,1 material=red translate=5,0,-2 translate_end=5,0,-4\n",
+            Path::new("."),
+            &mut Sampler::new(0),
+        )
+        .unwrap();
+
+        for (x, time, t) in [(0.0, 0.0, 1.0), (0.0, 0.5, 2.0), (5.0, 1.0, 3.0)] {
+            let r = Ray::with_time(Point3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+            let rec = scene
+                .world
+                .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+                .unwrap();
+            assert!((rec.t - t).abs() < 1e-9);
+        }
+        assert!((scene.world.bounding_box().z.min + 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn marks_lights() {
+        let scene = parse_scene(
+            "material lamp diffuse_light emit=4,4,4\n\
+             material grey lambertian albedo=0.5,0.5,0.5\n\
+             sphere center=0,-100,0 radius=99 material=grey\n\
+             light quad q=-1,2,-1 u=2,0,0 v=0,0,2 material=lamp\n\
+             light sphere center=0,0,0 radius=1 material=lamp translate=5,0,0\n",
+            Path::new("."),
+            &mut Sampler::new(0),
+        )
+        .unwrap();
+        assert_eq!(scene.lights.into_objects().len(), 2);
+        assert_eq!(scene.world.into_objects().len(), 3);
+
+        assert_eq!(
+            error_of("material m diffuse_light emit=1,1,1\nlight triangle p0=0,0,0 p1=1,0,0 p2=0,1,0 material=m"),
+            (2, String::from("'triangle' cannot be a light, expected sphere, quad or box"))
+        );
+        assert_eq!(
+            error_of("material m diffuse_light emit=1,1,1\nlight box min=0,0,0 max=1,1,1 material=m translate_end=0,1,0"),
+            (2, String::from("lights cannot move"))
+        );
+    }
+
+    #[test]
+    fn shades_with_textures() {
+        let scene = parse_scene(
+            "texture dark solid color=0.1,0.1,0.1\n\
+             texture tiles checker scale=1 even=dark odd=0.9,0.9,0.9\n\
+             material floor lambertian albedo=tiles\n\
+             quad q=-4,0,-4 u=8,0,0 v=0,0,8 material=floor\n",
+            Path::new("."),
+            &mut Sampler::new(0),
+        )
+        .unwrap();
+
+        let mut sampler = Sampler::new(0);
+        let mut albedo_at = |x: f64| {
+            let r = Ray::new(Point3::new(x, 1.0, 0.5), Vec3::new(0.0, -1.0, 0.0));
+            let rec = scene
+                .world
+                .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+                .unwrap();
+            let mat = rec.mat.clone().unwrap();
+            let Some(ScatterRecord::Sampled { attenuation, .. }) =
+                mat.scatter(&r, &rec, &mut sampler)
+            else {
+                panic!("expected sampled scattering");
+            };
+            attenuation
+        };
+        assert_eq!(albedo_at(0.5), Color::new(0.1, 0.1, 0.1));
+        assert_eq!(albedo_at(1.5), Color::new(0.9, 0.9, 0.9));
+
+        assert_eq!(
+            error_of("material m lambertian albedo=wood"),
+            (1, String::from("unknown texture 'wood'"))
+        );
+        assert_eq!(
+            error_of("texture t image file=missing.png"),
+            (
+                1,
+                String::from(
+                    "failed to load image: ./missing.png: No such file or directory (os error 2)"
+                )
+            )
+        );
+        let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let error = parse_scene(
+            "texture t image file=textures/quadrants.ppm wrap=tile",
+            &scenes,
+            &mut Sampler::new(0),
+        );
+        assert!(matches!(
+            error,
+            Err(SceneError::Parse { line: 1, message })
+                if message == "'wrap' expects repeat, mirror or clamp, once or for u,v, found 'tile'"
+        ));
+    }
+
+    #[test]
+    fn seeds_procedural_textures() {
+        let mut sampler = Sampler::new(0);
+        let mut albedo_with_seed = |seed: u64| {
+            let scene = parse_scene(
+                "texture veins marble scale=4 low=0.2 high=1,0.5,0\n\
+                 material m lambertian albedo=veins\n\
+                 sphere center=0,0,0 radius=1 material=m\n",
+                Path::new("."),
+                &mut Sampler::new(seed),
+            )
+            .unwrap();
+            let r = Ray::new(Point3::new(0.3, 0.2, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            let rec = scene
+                .world
+                .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+                .unwrap();
+            let mat = rec.mat.clone().unwrap();
+            let Some(ScatterRecord::Sampled { attenuation, .. }) =
+                mat.scatter(&r, &rec, &mut Sampler::new(0))
+            else {
+                panic!("expected sampled scattering");
+            };
+            attenuation
+        };
+        assert_eq!(albedo_with_seed(3), albedo_with_seed(3));
+        assert_ne!(albedo_with_seed(3), albedo_with_seed(4));
+
+        assert_eq!(
+            error_of("texture t noise octaves=100000000000"),
+            (
+                1,
+                String::from("'octaves' expects an integer from 1 to 16, found '100000000000'")
+            )
+        );
+        assert_eq!(
+            error_of("material m metal albedo=1,1,1 fuzz=rough"),
+            (1, String::from("unknown texture 'rough'"))
+        );
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        assert_eq!(
+            error_of("material red lambertian albedo=1,0,0\n\nsphere center=0,0,0 radius=1 material=blue"),
+            (3, String::from("unknown material 'blue'"))
+        );
+        assert_eq!(
+            error_of("\n\nsphere center=0,0 radius=1 material=red"),
+            (
+                3,
+                String::from("'center' expects three comma separated numbers, found '0,0'")
+            )
+        );
+        assert_eq!(
+            error_of("material red lambertian albedo=1,0,0\nsphere center=0,0,0 material=red"),
+            (2, String::from("missing required parameter 'radius'"))
+        );
+        assert_eq!(
+            error_of("camera background=black"),
+            (
+                1,
+                String::from("'background' expects three comma separated numbers, found 'black'")
+            )
+        );
+        assert_eq!(
+            error_of("camera image_width=-5"),
+            (
+                1,
+                String::from("'image_width' expects a positive integer, found '-5'")
+            )
+        );
+        assert_eq!(
+            error_of("camera samples_per_pixel=0"),
+            (
+                1,
+                String::from("'samples_per_pixel' expects a positive integer, found '0'")
+            )
+        );
+        assert_eq!(
+            error_of("\ncamera aspect_ratio=0"),
+            (
+                2,
+                String::from("'aspect_ratio' expects a positive number, found '0'")
+            )
+        );
+        assert_eq!(
+            error_of("material m metal albedo=1,1,1\ntriangle p0=0,0,0 p1=1,0,0 p2=0,1,0 n0=0,0,1 material=m"),
+            (2, String::from("give all of n0, n1 and n2 or none"))
+        );
+        assert_eq!(
+            error_of("obj file=missing.obj"),
+            (
+                1,
+                String::from(
+                    "failed to load mesh: ./missing.obj: No such file or directory (os error 2)"
+                )
+            )
+        );
+        assert_eq!(
+            error_of("ply file=missing.ply"),
+            (
+                1,
+                String::from(
+                    "failed to load mesh: ./missing.ply: No such file or directory (os error 2)"
+                )
+            )
+        );
+        assert_eq!(
+            error_of("camera vfov=20 zoom=3"),
+            (1, String::from("unknown parameter 'zoom'"))
+        );
+        assert_eq!(
+            error_of("camera vfov=180"),
+            (
+                1,
+                String::from("'vfov' expects a number between 0 and 180, found '180'")
+            )
+        );
+        assert_eq!(
+            error_of("camera focus_dist=0"),
+            (
+                1,
+                String::from("'focus_dist' expects a positive number, found '0'")
+            )
+        );
+        assert_eq!(
+            error_of("camera shutter_open=0.5 shutter_close=0.25"),
+            (
+                1,
+                String::from("'shutter_close' must not be earlier than 'shutter_open'")
+            )
+        );
+        assert_eq!(
+            error_of("material m plastic"),
+            (1, String::from("unknown material type 'plastic'"))
+        );
+        assert_eq!(
+            error_of("# header\ncube size=1"),
+            (2, String::from("unknown directive 'cube'"))
+        );
+        assert_eq!(
+            error_of("camera\ncamera vfov"),
+            (2, String::from("the camera is already defined"))
+        );
+    }
+}
@@ -1,32 +1,67 @@
-use std::rc::Rc;
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
+    aabb::Aabb,
     hittable::{HitRecord, Hittable},
     interval::Interval,
     material::Material,
+    onb::Onb,
     ray::Ray,
-    vec3::Point3,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
 };
 
 pub(crate) struct Sphere {
-    center: Point3,
+    // The center's path over time, from center(0) to center(1) for a moving sphere
+    center: Ray,
     radius: f64,
-    mat: Rc<dyn Material>,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
 }
 
 impl Sphere {
-    pub(crate) fn new(center: Point3, radius: f64, mat: Rc<dyn Material>) -> Self {
+    pub(crate) fn new(center: Point3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        let radius = f64::max(radius, 0.0);
+        let rvec = Vec3::new(radius, radius, radius);
         Self {
-            center,
+            center: Ray::new(center, Vec3::origin()),
             mat,
-            radius: f64::max(radius, 0.0),
+            radius,
+            bbox: Aabb::from_points(center - rvec, center + rvec),
         }
     }
-}
 
-impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
-        let oc = self.center - r.origin();
+    // A sphere moving linearly from center1 at time 0 to center2 at time 1
+    pub(crate) fn moving(
+        center1: Point3,
+        center2: Point3,
+        radius: f64,
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        let radius = f64::max(radius, 0.0);
+        let rvec = Vec3::new(radius, radius, radius);
+        let box1 = Aabb::from_points(center1 - rvec, center1 + rvec);
+        let box2 = Aabb::from_points(center2 - rvec, center2 + rvec);
+        Self {
+            center: Ray::new(center1, center2 - center1),
+            mat,
+            radius,
+            bbox: Aabb::surrounding(&box1, &box2),
+        }
+    }
+
+    // Maps a point p on the unit sphere to u, the angle around the y axis from x = -1, and v,
+    // the angle from y = -1 up to y = +1, both scaled to [0, 1]
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // Surfaces never sample while intersecting, so pdf_value can aim rays without a sampler
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let current_center = self.center.at(r.time());
+        let oc = current_center - r.origin();
         let a = r.direction().length_squared();
         let h = r.direction().dot(&oc);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -49,9 +84,80 @@ impl Hittable for Sphere {
         let mut rec = HitRecord::new();
         rec.t = root;
         rec.p = r.at(rec.t);
-        let outward_normal = (rec.p - self.center) / self.radius;
+        let outward_normal = (rec.p - current_center) / self.radius;
         rec.set_face_normal(r, outward_normal);
+        (rec.u, rec.v) = Self::get_sphere_uv(&outward_normal);
         rec.mat = Some(self.mat.clone());
         Some(rec)
     }
 }
+
+impl Hittable for Sphere {
+    fn hit(&self, r: &Ray, ray_t: &Interval, _sampler: &mut Sampler) -> Option<HitRecord> {
+        self.intersect(r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // Directions are drawn uniformly from the cone the sphere subtends at origin, using its
+    // position at time 0. From inside the sphere every direction reaches it, so they are drawn
+    // uniformly from the whole sphere of directions instead.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self
+            .intersect(
+                &Ray::new(*origin, *direction),
+                &Interval::new(0.001, f64::INFINITY),
+            )
+            .is_none()
+        {
+            return 0.0;
+        }
+        let distance_squared = (self.center.at(0.0) - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let direction = self.center.at(0.0) - *origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return Vec3::random_unit_vector(sampler);
+        }
+        let r1 = sampler.random_f64();
+        let r2 = sampler.random_f64();
+        let z = 1.0 + r2 * ((1.0 - radius_squared / distance_squared).sqrt() - 1.0);
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+        Onb::new(direction).transform(Vec3::new(x, y, z))
+    }
+
+    // Uniformly over the whole sphere at time 0, including the half facing away from wherever
+    // the light is going
+    fn sample_surface(&self, sampler: &mut Sampler) -> Option<(HitRecord, f64)> {
+        let outward_normal = Vec3::random_unit_vector(sampler);
+        let mut rec = HitRecord::new();
+        rec.p = self.center.at(0.0) + self.radius * outward_normal;
+        rec.normal = outward_normal;
+        rec.front_face = true;
+        (rec.u, rec.v) = Self::get_sphere_uv(&outward_normal);
+        rec.mat = Some(self.mat.clone());
+        Some((rec, 1.0 / (4.0 * PI * self.radius * self.radius)))
+    }
+
+    fn surface_pdf(&self, p: &Point3, _normal: &Vec3) -> f64 {
+        let distance = (*p - self.center.at(0.0)).length();
+        if (distance - self.radius).abs() > 1e-6 * (1.0 + self.radius) {
+            return 0.0;
+        }
+        1.0 / (4.0 * PI * self.radius * self.radius)
+    }
+}
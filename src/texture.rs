@@ -0,0 +1,458 @@
+use std::{path::Path, sync::Arc};
+
+use image::{ColorType, ImageError};
+
+use crate::{
+    color::{srgb_to_linear, Color},
+    noise::{Perlin, Worley},
+    sampler::Sampler,
+    utility::degrees_to_radians,
+    vec3::Point3,
+};
+
+// A color that varies over a surface, looked up by the hit's surface coordinates u and v and
+// its position p
+pub(crate) trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub(crate) struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub(crate) fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+// Alternates between two textures in a 3D grid of cubes scale units across, so the pattern
+// carries through any surface cut from it
+pub(crate) struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub(crate) fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub(crate) fn from_colors(scale: f64, even: Color, odd: Color) -> Self {
+        Self::new(
+            scale,
+            Arc::new(SolidColor::new(even)),
+            Arc::new(SolidColor::new(odd)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x = (self.inv_scale * p.x()).floor() as i64;
+        let y = (self.inv_scale * p.y()).floor() as i64;
+        let z = (self.inv_scale * p.z()).floor() as i64;
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// How texture coordinates outside [0, 1] map back onto an image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum WrapMode {
+    Repeat,
+    // Mirrors every other repeat so edges meet seamlessly
+    Mirror,
+    Clamp,
+}
+
+impl WrapMode {
+    // Maps a texel index onto one inside [0, size)
+    fn apply(self, texel: i64, size: usize) -> usize {
+        let size = size as i64;
+        let index = match self {
+            WrapMode::Repeat => texel.rem_euclid(size),
+            WrapMode::Mirror => {
+                let period = texel.rem_euclid(2 * size);
+                if period < size {
+                    period
+                } else {
+                    2 * size - 1 - period
+                }
+            }
+            WrapMode::Clamp => texel.clamp(0, size - 1),
+        };
+        index as usize
+    }
+}
+
+// How an image is read between texel centers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Filter {
+    Nearest,
+    // Blends the four nearest texels
+    Bilinear,
+}
+
+// An affine map applied to texture coordinates before the image lookup, stored as the top two
+// rows of a 3x3 matrix
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct UvTransform {
+    m: [[f64; 3]; 2],
+}
+
+impl UvTransform {
+    pub(crate) fn new(m: [[f64; 3]; 2]) -> Self {
+        Self { m }
+    }
+
+    pub(crate) fn identity() -> Self {
+        Self::new([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]])
+    }
+
+    // A scale, then a counter-clockwise rotation in degrees, then an offset. Scaling by 2
+    // repeats the image twice across the surface.
+    pub(crate) fn from_parts(scale: (f64, f64), degrees: f64, offset: (f64, f64)) -> Self {
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        Self::new([
+            [cos * scale.0, -sin * scale.1, offset.0],
+            [sin * scale.0, cos * scale.1, offset.1],
+        ])
+    }
+
+    fn apply(&self, u: f64, v: f64) -> (f64, f64) {
+        let m = &self.m;
+        (
+            m[0][0] * u + m[0][1] * v + m[0][2],
+            m[1][0] * u + m[1][1] * v + m[1][2],
+        )
+    }
+}
+
+// An image of linear colors, stored top row first, mapped with (0, 0) at its bottom left corner
+pub(crate) struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    wrap_u: WrapMode,
+    wrap_v: WrapMode,
+    filter: Filter,
+    transform: UvTransform,
+}
+
+impl ImageTexture {
+    pub(crate) fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        Self {
+            width,
+            height,
+            pixels,
+            wrap_u: WrapMode::Clamp,
+            wrap_v: WrapMode::Clamp,
+            filter: Filter::Bilinear,
+            transform: UvTransform::identity(),
+        }
+    }
+
+    // Reads a PNG, JPEG, PPM or Radiance HDR file. Integer images are taken to be sRGB encoded
+    // and are converted to linear colors; floating point images are already linear.
+    pub(crate) fn load(path: &Path) -> Result<Self, ImageError> {
+        let image = image::open(path)?;
+        let linear = matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+        let decode = |c: f32| {
+            if linear {
+                c as f64
+            } else {
+                srgb_to_linear(c as f64)
+            }
+        };
+        let image = image.into_rgb32f();
+        let pixels = image
+            .pixels()
+            .map(|p| Color::new(decode(p[0]), decode(p[1]), decode(p[2])))
+            .collect();
+        Ok(Self::new(
+            image.width() as usize,
+            image.height() as usize,
+            pixels,
+        ))
+    }
+
+    pub(crate) fn set_wrap(&mut self, wrap_u: WrapMode, wrap_v: WrapMode) {
+        self.wrap_u = wrap_u;
+        self.wrap_v = wrap_v;
+    }
+
+    pub(crate) fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    pub(crate) fn set_transform(&mut self, transform: UvTransform) {
+        self.transform = transform;
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let i = self.wrap_u.apply(x, self.width);
+        let j = self.wrap_v.apply(y, self.height);
+        self.pixels[j * self.width + i]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // Solid cyan makes a missing image easy to spot
+        if self.pixels.len() != self.width * self.height || self.pixels.is_empty() {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+        let (u, v) = self.transform.apply(u, v);
+        // Continuous texel coordinates, with texel centers at half integers
+        let x = u * self.width as f64;
+        let y = (1.0 - v) * self.height as f64;
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = (1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0);
+                let bottom = (1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1);
+                (1.0 - fy) * top + fy * bottom
+            }
+        }
+    }
+}
+
+// Procedural textures compute a pattern value in [0, 1] at each point and blend from their low
+// texture at 0 to their high texture at 1
+struct Ramp {
+    low: Arc<dyn Texture>,
+    high: Arc<dyn Texture>,
+}
+
+impl Ramp {
+    fn value(&self, t: f64, u: f64, v: f64, p: &Point3) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        (1.0 - t) * self.low.value(u, v, p) + t * self.high.value(u, v, p)
+    }
+}
+
+// Perlin noise at a frequency of scale per unit, summed over octaves as fractal Brownian motion,
+// or as turbulence when turbulent
+pub(crate) struct NoiseTexture {
+    perlin: Perlin,
+    scale: f64,
+    octaves: usize,
+    turbulent: bool,
+    ramp: Ramp,
+}
+
+impl NoiseTexture {
+    pub(crate) fn new(
+        sampler: &mut Sampler,
+        scale: f64,
+        octaves: usize,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            perlin: Perlin::new(sampler),
+            scale,
+            octaves,
+            turbulent: false,
+            ramp: Ramp { low, high },
+        }
+    }
+
+    pub(crate) fn set_turbulent(&mut self, turbulent: bool) {
+        self.turbulent = turbulent;
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let q = self.scale * *p;
+        let t = if self.turbulent {
+            self.perlin.turbulence(&q, self.octaves)
+        } else {
+            0.5 * (1.0 + self.perlin.fbm(&q, self.octaves))
+        };
+        self.ramp.value(t, u, v, p)
+    }
+}
+
+// Bands running along z, scale per unit, bent into veins by turbulence
+pub(crate) struct MarbleTexture {
+    perlin: Perlin,
+    scale: f64,
+    ramp: Ramp,
+}
+
+impl MarbleTexture {
+    pub(crate) fn new(
+        sampler: &mut Sampler,
+        scale: f64,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            perlin: Perlin::new(sampler),
+            scale,
+            ramp: Ramp { low, high },
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let phase = self.scale * p.z() + 10.0 * self.perlin.turbulence(p, 7);
+        self.ramp.value(0.5 * (1.0 + phase.sin()), u, v, p)
+    }
+}
+
+// Growth rings around the y axis, scale rings per unit, wobbled by noise. Each ring fades from
+// the high texture (early wood) to the low one (late wood).
+pub(crate) struct WoodTexture {
+    perlin: Perlin,
+    scale: f64,
+    ramp: Ramp,
+}
+
+impl WoodTexture {
+    pub(crate) fn new(
+        sampler: &mut Sampler,
+        scale: f64,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            perlin: Perlin::new(sampler),
+            scale,
+            ramp: Ramp { low, high },
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let radius = p.x().hypot(p.z()) + 0.1 * self.perlin.fbm(&(2.0 * *p), 3);
+        let ring = (self.scale * radius).rem_euclid(1.0);
+        self.ramp.value(1.0 - ring * ring, u, v, p)
+    }
+}
+
+// Worley cellular noise with scale cells per unit: the low texture at each cell's feature point,
+// blending to the high one a cell's width away
+pub(crate) struct WorleyTexture {
+    worley: Worley,
+    scale: f64,
+    ramp: Ramp,
+}
+
+impl WorleyTexture {
+    pub(crate) fn new(
+        sampler: &mut Sampler,
+        scale: f64,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            worley: Worley::new(sampler),
+            scale,
+            ramp: Ramp { low, high },
+        }
+    }
+}
+
+impl Texture for WorleyTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let t = self.worley.noise(&(self.scale * *p));
+        self.ramp.value(t, u, v, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        color::Color,
+        texture::{CheckerTexture, Filter, ImageTexture, Texture, UvTransform, WrapMode},
+        vec3::Point3,
+    };
+
+    #[test]
+    fn checker_alternates_in_3d() {
+        let black = Color::origin();
+        let white = Color::new(1.0, 1.0, 1.0);
+        let checker = CheckerTexture::from_colors(0.5, white, black);
+        let at = |x, y, z| checker.value(0.0, 0.0, &Point3::new(x, y, z));
+        assert_eq!(at(0.1, 0.1, 0.1), white);
+        assert_eq!(at(0.6, 0.1, 0.1), black);
+        assert_eq!(at(0.6, 0.6, 0.1), white);
+        assert_eq!(at(-0.1, 0.1, 0.1), black);
+    }
+
+    #[test]
+    fn image_wraps_coordinates() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let blue = Color::new(0.0, 0.0, 1.0);
+        // One row: red on the left, blue on the right
+        let mut image = ImageTexture::new(2, 1, vec![red, blue]);
+        let at = |image: &ImageTexture, u| image.value(u, 0.5, &Point3::origin());
+        assert_eq!(at(&image, 0.25), red);
+        assert_eq!(at(&image, 0.75), blue);
+        assert_eq!(at(&image, 1.25), blue);
+
+        image.set_wrap(WrapMode::Repeat, WrapMode::Repeat);
+        assert_eq!(at(&image, 1.25), red);
+        image.set_wrap(WrapMode::Mirror, WrapMode::Repeat);
+        assert_eq!(at(&image, 1.25), blue);
+        assert_eq!(at(&image, -0.25), red);
+    }
+
+    #[test]
+    fn image_filters_and_transforms_coordinates() {
+        let black = Color::origin();
+        let white = Color::new(1.0, 1.0, 1.0);
+        let mut image = ImageTexture::new(2, 1, vec![black, white]);
+        let at = |image: &ImageTexture, u, v| image.value(u, v, &Point3::origin());
+        // Halfway between the two texel centers
+        assert_eq!(at(&image, 0.5, 0.5), 0.5 * white);
+        assert_eq!(at(&image, 0.375, 0.5), 0.25 * white);
+        image.set_filter(Filter::Nearest);
+        assert_eq!(at(&image, 0.375, 0.5), black);
+
+        // A quarter turn then a shift of one reads u from 1 - v
+        image.set_transform(UvTransform::from_parts((1.0, 1.0), 90.0, (1.0, 0.0)));
+        assert_eq!(at(&image, 0.5, 0.75), black);
+        assert_eq!(at(&image, 0.5, 0.25), white);
+        image.set_transform(UvTransform::from_parts((2.0, 1.0), 0.0, (0.5, 0.0)));
+        assert_eq!(at(&image, 0.1, 0.5), white);
+    }
+
+    #[test]
+    fn loads_images_as_linear_colors() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/textures/quadrants.ppm");
+        let image = ImageTexture::load(&path).unwrap();
+        // The top left quadrant is sRGB (188, 40, 40), about half red in linear terms
+        let red = image.value(0.25, 0.75, &Point3::origin());
+        assert!((red.x() - 0.503).abs() < 1e-3);
+        assert!((red.y() - 0.021).abs() < 1e-3);
+
+        assert!(ImageTexture::load(&path.with_extension("png")).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    matrix::Matrix,
+    ray::Ray,
+    sampler::Sampler,
+    utility::degrees_to_radians,
+    vec3::{Point3, Vec3},
+};
+
+// An invertible affine transform, kept together with its inverse and the inverse transpose
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+    normal_matrix: Matrix,
+}
+
+impl Transform {
+    // None when the matrix is singular, e.g. a scale of zero along some axis
+    pub(crate) fn new(matrix: Matrix) -> Option<Self> {
+        Some(Self::with_inverse(matrix, matrix.inverse()?))
+    }
+
+    // For callers that can build the inverse more cheaply than a general inversion
+    fn with_inverse(matrix: Matrix, inverse: Matrix) -> Self {
+        Self {
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        }
+    }
+
+    pub(crate) fn is_identity(&self) -> bool {
+        self.matrix.is_identity()
+    }
+
+    pub(crate) fn point(&self, p: Point3) -> Point3 {
+        self.matrix.transform_point(p)
+    }
+
+    // Normals transform by the inverse transpose to stay perpendicular to the surface
+    pub(crate) fn normal(&self, n: Vec3) -> Vec3 {
+        self.normal_matrix.transform_vector(n).unit_vector()
+    }
+
+    // Brings a world space ray into the transform's object space. The direction is not
+    // renormalized, so hit distances t are the same in both spaces.
+    pub(crate) fn inverse_ray(&self, r: &Ray) -> Ray {
+        Ray::with_time(
+            self.inverse.transform_point(r.origin()),
+            self.inverse.transform_vector(r.direction()),
+            r.time(),
+        )
+    }
+
+    // Intersects object as seen through the transform, returning the hit in world space
+    pub(crate) fn hit(
+        &self,
+        object: &dyn Hittable,
+        r: &Ray,
+        ray_t: &Interval,
+        sampler: &mut Sampler,
+    ) -> Option<HitRecord> {
+        let mut rec = object.hit(&self.inverse_ray(r), ray_t, sampler)?;
+        rec.p = self.point(rec.p);
+        rec.normal = self.normal(rec.normal);
+        Some(rec)
+    }
+
+    // The density of directions towards object as seen through the transform. A unit direction
+    // w maps to object space as B w / |B w|, B being the inverse's linear part, which stretches
+    // solid angle by |det B| / |B w|^3.
+    pub(crate) fn pdf_value(
+        &self,
+        object: &dyn Hittable,
+        origin: &Point3,
+        direction: &Vec3,
+    ) -> f64 {
+        let local_direction = self.inverse.transform_vector(direction.unit_vector());
+        let local_origin = self.inverse.transform_point(*origin);
+        let length = local_direction.length();
+        object.pdf_value(&local_origin, &local_direction) * self.inverse.linear_determinant().abs()
+            / (length * length * length)
+    }
+
+    pub(crate) fn random(
+        &self,
+        object: &dyn Hittable,
+        origin: &Point3,
+        sampler: &mut Sampler,
+    ) -> Vec3 {
+        let local_origin = self.inverse.transform_point(*origin);
+        self.matrix
+            .transform_vector(object.random(&local_origin, sampler))
+    }
+
+    // Points picked on object, as seen through the transform. Area stretches by |det M| / |M^T n|
+    // around a point with world space normal n, M being the linear part of the matrix.
+    pub(crate) fn sample_surface(
+        &self,
+        object: &dyn Hittable,
+        sampler: &mut Sampler,
+    ) -> Option<(HitRecord, f64)> {
+        let (mut rec, pdf) = object.sample_surface(sampler)?;
+        rec.p = self.point(rec.p);
+        rec.normal = self.normal(rec.normal);
+        let pdf = pdf * self.area_density_scale(&rec.normal);
+        Some((rec, pdf))
+    }
+
+    pub(crate) fn surface_pdf(&self, object: &dyn Hittable, p: &Point3, normal: &Vec3) -> f64 {
+        let local_normal = self
+            .matrix
+            .transpose()
+            .transform_vector(*normal)
+            .unit_vector();
+        object.surface_pdf(&self.inverse.transform_point(*p), &local_normal)
+            * self.area_density_scale(normal)
+    }
+
+    fn area_density_scale(&self, normal: &Vec3) -> f64 {
+        self.matrix.transpose().transform_vector(*normal).length()
+            / self.matrix.linear_determinant().abs()
+    }
+
+    // The box around all eight transformed corners of bbox
+    pub(crate) fn bounds(&self, bbox: &Aabb) -> Aabb {
+        if bbox.is_empty() {
+            return *bbox;
+        }
+        let mut bounds = Aabb::empty();
+        for corner in corners(bbox) {
+            let p = self.point(corner);
+            bounds = Aabb::surrounding(&bounds, &Aabb::from_points(p, p));
+        }
+        bounds
+    }
+}
+
+fn corners(bbox: &Aabb) -> impl Iterator<Item = Point3> + '_ {
+    (0..8).map(|corner| {
+        Point3::new(
+            [bbox.x.min, bbox.x.max][corner & 1],
+            [bbox.y.min, bbox.y.max][(corner >> 1) & 1],
+            [bbox.z.min, bbox.z.max][corner >> 2],
+        )
+    })
+}
+
+// Places a shared object in the world through a transform; many Transformed instances can
+// refer to one object, such as a large mesh, without duplicating its geometry
+pub(crate) struct Transformed {
+    object: Arc<dyn Hittable>,
+    transform: Transform,
+    bbox: Aabb,
+}
+
+impl Transformed {
+    pub(crate) fn new(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        let bbox = transform.bounds(&object.bounding_box());
+        Self {
+            object,
+            transform,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, r: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> Option<HitRecord> {
+        self.transform.hit(self.object.as_ref(), r, ray_t, sampler)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.transform
+            .pdf_value(self.object.as_ref(), origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        self.transform.random(self.object.as_ref(), origin, sampler)
+    }
+
+    fn sample_surface(&self, sampler: &mut Sampler) -> Option<(HitRecord, f64)> {
+        self.transform.sample_surface(self.object.as_ref(), sampler)
+    }
+
+    fn surface_pdf(&self, p: &Point3, normal: &Vec3) -> f64 {
+        self.transform.surface_pdf(self.object.as_ref(), p, normal)
+    }
+}
+
+// A scale, then a rotation about x, y and z in turn (in degrees), then a translation. Unlike a
+// matrix, two placements can be blended into a sensible in-between pose.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Placement {
+    pub(crate) translate: Vec3,
+    pub(crate) rotate: Vec3,
+    pub(crate) scale: Vec3,
+}
+
+impl Placement {
+    pub(crate) fn identity() -> Self {
+        Self {
+            translate: Vec3::origin(),
+            rotate: Vec3::origin(),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub(crate) fn matrix(&self) -> Matrix {
+        Matrix::translation(self.translate) * self.rotation() * Matrix::scaling(self.scale)
+    }
+
+    // The transform and its inverse, which undoes each part in reverse order: the rotation's
+    // inverse is its transpose, so no general inversion is needed. None for a zero scale.
+    pub(crate) fn transform(&self) -> Option<Transform> {
+        let scale = self.scale;
+        if scale.x() == 0.0 || scale.y() == 0.0 || scale.z() == 0.0 {
+            return None;
+        }
+        let inverse = Matrix::scaling(Vec3::new(1.0 / scale.x(), 1.0 / scale.y(), 1.0 / scale.z()))
+            * self.rotation().transpose()
+            * Matrix::translation(-self.translate);
+        Some(Transform::with_inverse(self.matrix(), inverse))
+    }
+
+    fn rotation(&self) -> Matrix {
+        Matrix::rotation(Vec3::new(0.0, 0.0, 1.0), self.rotate.z())
+            * Matrix::rotation(Vec3::new(0.0, 1.0, 0.0), self.rotate.y())
+            * Matrix::rotation(Vec3::new(1.0, 0.0, 0.0), self.rotate.x())
+    }
+
+    fn lerp(&self, other: &Placement, t: f64) -> Placement {
+        let mix = |a: Vec3, b: Vec3| (1.0 - t) * a + t * b;
+        Placement {
+            translate: mix(self.translate, other.translate),
+            rotate: mix(self.rotate, other.rotate),
+            scale: mix(self.scale, other.scale),
+        }
+    }
+}
+
+// Number of poses sampled along a motion to bound it
+const MOTION_BOUND_STEPS: usize = 64;
+
+// An object moving from one placement at time 0 to another at time 1, interpolating the
+// translation, rotation and scale separately so rotations sweep rather than shear
+pub(crate) struct MovingTransformed {
+    object: Arc<dyn Hittable>,
+    start: Placement,
+    end: Placement,
+    bbox: Aabb,
+}
+
+impl MovingTransformed {
+    // None when either end of the motion is singular
+    pub(crate) fn new(object: Arc<dyn Hittable>, start: Placement, end: Placement) -> Option<Self> {
+        start.transform()?;
+        end.transform()?;
+
+        // Union the boxes of closely spaced poses. Translation and scale move points linearly,
+        // so only rotation can carry them outside, by at most r * angle^2 / 8 between poses.
+        let object_bbox = object.bounding_box();
+        let mut bbox = Aabb::empty();
+        for step in 0..=MOTION_BOUND_STEPS {
+            let t = step as f64 / MOTION_BOUND_STEPS as f64;
+            if let Some(transform) = start.lerp(&end, t).transform() {
+                bbox = Aabb::surrounding(&bbox, &transform.bounds(&object_bbox));
+            }
+        }
+        let turn = end.rotate - start.rotate;
+        if !bbox.is_empty() && turn != Vec3::origin() {
+            let angle = degrees_to_radians(turn.x().abs() + turn.y().abs() + turn.z().abs())
+                / MOTION_BOUND_STEPS as f64;
+            let reach = corners(&object_bbox)
+                .map(|corner| corner.length())
+                .fold(0.0, f64::max);
+            let scale = [start.scale, end.scale]
+                .iter()
+                .map(|s| s.x().abs().max(s.y().abs()).max(s.z().abs()))
+                .fold(0.0, f64::max);
+            let pad = reach * scale * angle * angle / 8.0;
+            bbox = Aabb::new(bbox.x.expand(pad), bbox.y.expand(pad), bbox.z.expand(pad));
+        }
+
+        Some(Self {
+            object,
+            start,
+            end,
+            bbox,
+        })
+    }
+}
+
+impl Hittable for MovingTransformed {
+    fn hit(&self, r: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> Option<HitRecord> {
+        let transform = self.start.lerp(&self.end, r.time()).transform()?;
+        transform.hit(self.object.as_ref(), r, ray_t, sampler)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        interval::Interval,
+        material::Lambertian,
+        matrix::Matrix,
+        ray::Ray,
+        sampler::Sampler,
+        sphere::Sphere,
+        transform::{MovingTransformed, Placement, Transform, Transformed},
+        vec3::{Point3, Vec3},
+    };
+
+    #[test]
+    fn hits_match_the_equivalent_world_object() {
+        let mut sampler = Sampler::new(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let unit = Arc::new(Sphere::new(Point3::origin(), 1.0, mat.clone()));
+        let matrix = Matrix::translation(Vec3::new(2.0, 1.0, -3.0))
+            * Matrix::rotation(Vec3::new(0.0, 1.0, 0.0), 40.0)
+            * Matrix::scaling(Vec3::new(1.5, 1.5, 1.5));
+        let instance = Transformed::new(unit, Transform::new(matrix).unwrap());
+        let sphere = Sphere::new(Point3::new(2.0, 1.0, -3.0), 1.5, mat);
+
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        for target in [Point3::new(2.0, 1.0, -3.0), Point3::new(2.9, 1.7, -2.5)] {
+            let origin = Point3::new(-1.0, 0.5, 4.0);
+            let r = Ray::new(origin, target - origin);
+            let a = instance.hit(&r, &ray_t, &mut sampler).unwrap();
+            let b = sphere.hit(&r, &ray_t, &mut sampler).unwrap();
+            assert!((a.t - b.t).abs() < 1e-9);
+            assert!((a.p - b.p).length() < 1e-9);
+            assert!((a.normal - b.normal).length() < 1e-9);
+            assert_eq!(a.front_face, b.front_face);
+        }
+
+        let bbox = instance.bounding_box();
+        assert!(bbox.x.min <= 0.5 && bbox.x.max >= 3.5);
+        assert!(bbox.y.min <= -0.5 && bbox.y.max >= 2.5);
+    }
+
+    #[test]
+    fn moving_objects_follow_their_placements() {
+        let mut sampler = Sampler::new(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let unit = Arc::new(Sphere::new(Point3::origin(), 1.0, mat));
+        let start = Placement::identity();
+        let end = Placement {
+            translate: Vec3::new(4.0, 0.0, 0.0),
+            rotate: Vec3::new(0.0, 90.0, 0.0),
+            scale: Vec3::new(2.0, 2.0, 2.0),
+        };
+        let moving = MovingTransformed::new(unit, start, end).unwrap();
+
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let mut t_at = |x: f64, time: f64| {
+            let r = Ray::with_time(Point3::new(x, 5.0, 0.0), down, time);
+            moving.hit(&r, &ray_t, &mut sampler).map(|rec| rec.t)
+        };
+        assert_eq!(t_at(0.0, 0.0), Some(4.0));
+        assert!(t_at(4.0, 0.0).is_none());
+        // Halfway along, the sphere is centered at x = 2 with radius 1.5
+        assert!((t_at(2.0, 0.5).unwrap() - 3.5).abs() < 1e-9);
+        assert!((t_at(4.0, 1.0).unwrap() - 3.0).abs() < 1e-9);
+
+        let bbox = moving.bounding_box();
+        assert!(bbox.x.min <= -1.0 && bbox.x.max >= 6.0);
+        assert!(bbox.y.min <= -2.0 && bbox.y.max >= 2.0);
+    }
+
+    #[test]
+    fn placements_invert_part_by_part() {
+        let placement = Placement {
+            translate: Vec3::new(1.0, -2.0, 3.0),
+            rotate: Vec3::new(30.0, -45.0, 60.0),
+            scale: Vec3::new(2.0, 0.5, -1.0),
+        };
+        let transform = placement.transform().unwrap();
+        let inverse = placement.matrix().inverse().unwrap();
+        let p = Point3::new(0.3, -1.2, 2.5);
+        assert!(
+            (transform.inverse.transform_point(p) - inverse.transform_point(p)).length() < 1e-9
+        );
+        let n = Vec3::new(0.6, 0.0, -0.8);
+        let normal = inverse.transpose().transform_vector(n).unit_vector();
+        assert!((transform.normal(n) - normal).length() < 1e-9);
+
+        let flat = Placement {
+            scale: Vec3::new(1.0, 0.0, 1.0),
+            ..placement
+        };
+        assert!(flat.transform().is_none());
+    }
+}
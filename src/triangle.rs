@@ -0,0 +1,344 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+// Texture coordinates of a vertex
+pub(crate) type Uv = (f64, f64);
+
+// Default texture coordinates for triangles without any, so u and v run along the two edges
+pub(crate) const DEFAULT_UVS: [Uv; 3] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+
+// Standalone triangle that owns its vertex data
+pub(crate) struct Triangle {
+    positions: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [Uv; 3],
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Triangle {
+    pub(crate) fn new(positions: [Point3; 3], mat: Arc<dyn Material>) -> Self {
+        Self::with_attributes(positions, None, DEFAULT_UVS, mat)
+    }
+
+    pub(crate) fn with_attributes(
+        positions: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: [Uv; 3],
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            positions,
+            normals,
+            uvs,
+            mat,
+            bbox: triangle_bounds(&positions),
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: &Interval, _sampler: &mut Sampler) -> Option<HitRecord> {
+        let (t, b1, b2) = intersect(&self.positions, r, ray_t)?;
+        Some(hit_record(
+            r,
+            t,
+            [1.0 - b1 - b2, b1, b2],
+            &self.positions,
+            self.normals.as_ref(),
+            &self.uvs,
+            &self.mat,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+// Indexed triangle mesh. Vertex attributes are stored once and shared by every face that
+// references them; normals, uvs and colors are either empty or hold one entry per position.
+pub(crate) struct TriangleMesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Uv>,
+    colors: Vec<Color>,
+    faces: Vec<[u32; 3]>,
+    mat: Arc<dyn Material>,
+}
+
+impl TriangleMesh {
+    // Panics if a face references a missing vertex or an attribute array has the wrong length
+    pub(crate) fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<Uv>,
+        colors: Vec<Color>,
+        faces: Vec<[u32; 3]>,
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        assert!(normals.is_empty() || normals.len() == positions.len());
+        assert!(uvs.is_empty() || uvs.len() == positions.len());
+        assert!(colors.is_empty() || colors.len() == positions.len());
+        assert!(faces
+            .iter()
+            .flatten()
+            .all(|&i| (i as usize) < positions.len()));
+        Self {
+            positions,
+            normals,
+            uvs,
+            colors,
+            faces,
+            mat,
+        }
+    }
+
+    pub(crate) fn face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    // One hittable per face, each sharing this mesh's vertex data, ready to go into a list or
+    // acceleration structure
+    pub(crate) fn triangles(self: &Arc<Self>) -> Vec<Arc<dyn Hittable>> {
+        (0..self.faces.len() as u32)
+            .map(|face| {
+                Arc::new(MeshTriangle {
+                    mesh: self.clone(),
+                    face,
+                }) as Arc<dyn Hittable>
+            })
+            .collect()
+    }
+
+    pub(crate) fn face_positions(&self, face: u32) -> [Point3; 3] {
+        self.faces[face as usize].map(|i| self.positions[i as usize])
+    }
+
+    pub(crate) fn face_hit(&self, face: u32, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let (t, b1, b2) = intersect(&self.face_positions(face), r, ray_t)?;
+        Some(self.face_record(face, r, t, b1, b2))
+    }
+
+    // The unit normal given by the face's winding, and the face's area
+    pub(crate) fn face_normal_and_area(&self, face: u32) -> (Vec3, f64) {
+        let p = self.face_positions(face);
+        let n = (p[1] - p[0]).cross(&(p[2] - p[0]));
+        let length = n.length();
+        (n / length, 0.5 * length)
+    }
+
+    // The point of face with barycentric weights b1 and b2 on its second and third vertices, as
+    // a hit seen from the front of the face
+    pub(crate) fn face_point(&self, face: u32, b1: f64, b2: f64) -> HitRecord {
+        let p = self.face_positions(face);
+        let point = (1.0 - b1 - b2) * p[0] + b1 * p[1] + b2 * p[2];
+        let (normal, _) = self.face_normal_and_area(face);
+        self.face_record(face, &Ray::new(point + normal, -normal), 1.0, b1, b2)
+    }
+
+    fn face_record(&self, face: u32, r: &Ray, t: f64, b1: f64, b2: f64) -> HitRecord {
+        let positions = self.face_positions(face);
+        let indices = self.faces[face as usize];
+        let normals = (!self.normals.is_empty()).then(|| indices.map(|i| self.normals[i as usize]));
+        let uvs = if self.uvs.is_empty() {
+            DEFAULT_UVS
+        } else {
+            indices.map(|i| self.uvs[i as usize])
+        };
+        let weights = [1.0 - b1 - b2, b1, b2];
+        let mut rec = hit_record(r, t, weights, &positions, normals.as_ref(), &uvs, &self.mat);
+        if !self.colors.is_empty() {
+            let c = indices.map(|i| self.colors[i as usize]);
+            rec.vertex_color = Some(weights[0] * c[0] + weights[1] * c[1] + weights[2] * c[2]);
+        }
+        rec
+    }
+}
+
+// A single face of a TriangleMesh
+struct MeshTriangle {
+    mesh: Arc<TriangleMesh>,
+    face: u32,
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, ray_t: &Interval, _sampler: &mut Sampler) -> Option<HitRecord> {
+        self.mesh.face_hit(self.face, r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        triangle_bounds(&self.mesh.face_positions(self.face))
+    }
+}
+
+fn triangle_bounds(p: &[Point3; 3]) -> Aabb {
+    Aabb::surrounding(
+        &Aabb::from_points(p[0], p[1]),
+        &Aabb::from_points(p[2], p[2]),
+    )
+}
+
+// Möller–Trumbore ray/triangle intersection. Returns the ray parameter and the barycentric
+// weights of the second and third vertices.
+fn intersect(p: &[Point3; 3], r: &Ray, ray_t: &Interval) -> Option<(f64, f64, f64)> {
+    let edge1 = p[1] - p[0];
+    let edge2 = p[2] - p[0];
+    let pvec = r.direction().cross(&edge2);
+    let det = edge1.dot(&pvec);
+
+    // No hit if the ray is parallel to the triangle
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin() - p[0];
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(&edge1);
+    let b2 = r.direction().dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+fn hit_record(
+    r: &Ray,
+    t: f64,
+    weights: [f64; 3],
+    positions: &[Point3; 3],
+    normals: Option<&[Vec3; 3]>,
+    uvs: &[Uv; 3],
+    mat: &Arc<dyn Material>,
+) -> HitRecord {
+    let mut rec = HitRecord::new();
+    rec.t = t;
+    rec.p = r.at(t);
+    rec.u = weights[0] * uvs[0].0 + weights[1] * uvs[1].0 + weights[2] * uvs[2].0;
+    rec.v = weights[0] * uvs[0].1 + weights[1] * uvs[1].1 + weights[2] * uvs[2].1;
+
+    // The winding order decides which side is the front, while interpolated vertex normals
+    // only smooth the shading and are turned to face the same way as the geometric normal
+    let geometric = (positions[1] - positions[0])
+        .cross(&(positions[2] - positions[0]))
+        .unit_vector();
+    rec.set_face_normal(r, geometric);
+    if let Some(n) = normals {
+        let shading = weights[0] * n[0] + weights[1] * n[1] + weights[2] * n[2];
+        if shading.length_squared() > 0.0 {
+            let shading = shading.unit_vector();
+            rec.normal = if shading.dot(&rec.normal) < 0.0 {
+                -shading
+            } else {
+                shading
+            };
+        }
+    }
+    rec.mat = Some(mat.clone());
+    rec
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        interval::Interval,
+        material::Lambertian,
+        ray::Ray,
+        sampler::Sampler,
+        triangle::{Triangle, TriangleMesh},
+        vec3::{Point3, Vec3},
+    };
+
+    fn unit_triangle() -> [Point3; 3] {
+        [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ]
+    }
+
+    #[test]
+    fn triangle_hit_and_miss() {
+        let mut sampler = Sampler::new(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let triangle = Triangle::new(unit_triangle(), mat);
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+
+        let r = Ray::new(Point3::new(0.25, 0.5, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = triangle.hit(&r, &ray_t, &mut sampler).unwrap();
+        assert_eq!(rec.t, 3.0);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+        assert_eq!((rec.u, rec.v), (0.25, 0.5));
+
+        let behind = Ray::new(Point3::new(0.25, 0.25, -3.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(
+            !triangle
+                .hit(&behind, &ray_t, &mut sampler)
+                .unwrap()
+                .front_face
+        );
+
+        let outside = Ray::new(Point3::new(0.75, 0.75, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangle.hit(&outside, &ray_t, &mut sampler).is_none());
+        let parallel = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(triangle.hit(&parallel, &ray_t, &mut sampler).is_none());
+    }
+
+    #[test]
+    fn mesh_interpolates_vertex_attributes() {
+        let mut sampler = Sampler::new(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut positions = unit_triangle().to_vec();
+        positions.push(Point3::new(1.0, 1.0, 0.0));
+        let tilted = Vec3::new(1.0, 0.0, 1.0).unit_vector();
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        let mesh = Arc::new(TriangleMesh::new(
+            positions,
+            vec![up, tilted, up, tilted],
+            vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)],
+            vec![Color::new(1.0, 0.0, 0.0); 4],
+            vec![[0, 1, 2], [1, 3, 2]],
+            mat,
+        ));
+        let triangles = mesh.triangles();
+        assert_eq!(triangles.len(), mesh.face_count());
+
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        let r = Ray::new(Point3::new(0.75, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangles[0].hit(&r, &ray_t, &mut sampler).is_none());
+        let rec = triangles[1].hit(&r, &ray_t, &mut sampler).unwrap();
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+        assert_eq!(rec.vertex_color, Some(Color::new(1.0, 0.0, 0.0)));
+
+        // Halfway along the edge between a tilted and an upright vertex normal
+        let r = Ray::new(Point3::new(0.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = triangles[0].hit(&r, &ray_t, &mut sampler).unwrap();
+        let expected = (0.5 * up + 0.5 * tilted).unit_vector();
+        assert!((rec.normal - expected).length() < 1e-12);
+    }
+}
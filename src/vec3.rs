@@ -1,7 +1,7 @@
 use core::fmt;
-use std::ops;
+use std::{f64::consts::PI, ops};
 
-use crate::utility::{random_f64, random_f64_range};
+use crate::sampler::Sampler;
 
 pub(crate) type Point3 = Vec3;
 
@@ -19,15 +19,19 @@ impl Vec3 {
         Self::new(0.0, 0.0, 0.0)
     }
 
-    pub(crate) fn random() -> Self {
-        Self::new(random_f64(), random_f64(), random_f64())
+    pub(crate) fn random(sampler: &mut Sampler) -> Self {
+        Self::new(
+            sampler.random_f64(),
+            sampler.random_f64(),
+            sampler.random_f64(),
+        )
     }
 
-    pub(crate) fn random_in_unit_disk() -> Self {
+    pub(crate) fn random_in_unit_disk(sampler: &mut Sampler) -> Self {
         loop {
             let p = Self::new(
-                random_f64_range(-1.0, 1.0),
-                random_f64_range(-1.0, 1.0),
+                sampler.random_f64_range(-1.0, 1.0),
+                sampler.random_f64_range(-1.0, 1.0),
                 0.0,
             );
             if p.length_squared() < 1.0 {
@@ -36,17 +40,17 @@ impl Vec3 {
         }
     }
 
-    pub(crate) fn random_range(min: f64, max: f64) -> Self {
+    pub(crate) fn random_range(sampler: &mut Sampler, min: f64, max: f64) -> Self {
         Self::new(
-            random_f64_range(min, max),
-            random_f64_range(min, max),
-            random_f64_range(min, max),
+            sampler.random_f64_range(min, max),
+            sampler.random_f64_range(min, max),
+            sampler.random_f64_range(min, max),
         )
     }
 
-    pub(crate) fn random_unit_vector() -> Self {
+    pub(crate) fn random_unit_vector(sampler: &mut Sampler) -> Self {
         loop {
-            let p = Self::random_range(-1.0, 1.0);
+            let p = Self::random_range(sampler, -1.0, 1.0);
             let lensq = p.length_squared();
             if 1e-160 < lensq && lensq <= 1.0 {
                 return p / lensq.sqrt();
@@ -54,6 +58,16 @@ impl Vec3 {
         }
     }
 
+    // A direction on the hemisphere around +z, with density proportional to its z component
+    pub(crate) fn random_cosine_direction(sampler: &mut Sampler) -> Self {
+        let r1 = sampler.random_f64();
+        let r2 = sampler.random_f64();
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        Self::new(x, y, (1.0 - r2).sqrt())
+    }
+
     pub(crate) fn x(&self) -> f64 {
         self[0]
     }
@@ -95,9 +109,8 @@ impl Vec3 {
     }
 
     pub(crate) fn refract(&self, n: &Vec3, etai_over_etat: f64) -> Vec3 {
-        let uv = -*self;
-        let cos_theta = f64::min(uv.dot(n), 1.0);
-        let r_out_perp = etai_over_etat * (uv + cos_theta * *n);
+        let cos_theta = f64::min(-self.dot(n), 1.0);
+        let r_out_perp = etai_over_etat * (*self + cos_theta * *n);
         let r_out_parallel = -((1.0 - r_out_perp.length_squared()).abs()).sqrt() * *n;
         r_out_perp + r_out_parallel
     }
@@ -264,8 +277,24 @@ mod tests {
         assert_eq!(x, Vec3::new(4.0, 4.0, 4.0));
     }
 
+    #[test]
+    fn reflect_and_refract() {
+        // A ray coming down at 45 degrees onto a surface facing up
+        let d = Vec3::new(1.0, -1.0, 0.0) / 2.0_f64.sqrt();
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(d.reflection(&n), Vec3::new(1.0, 1.0, 0.0) / 2.0_f64.sqrt());
+        assert!((d.refract(&n, 1.0) - d).length() < 1e-12);
+
+        // Entering glass, the sine of the angle to the normal shrinks by the refractive index
+        let r = d.refract(&n, 1.0 / 1.5);
+        assert!((r.length() - 1.0).abs() < 1e-12);
+        assert!((r.x() - 0.5_f64.sqrt() / 1.5).abs() < 1e-12);
+        assert!(r.y() < 0.0);
+    }
+
     #[test]
     #[should_panic]
+    #[allow(clippy::no_effect)]
     fn test_vec3_index_should_panic() {
         let v = Vec3::origin();
         v[4];
@@ -0,0 +1,328 @@
+// Heterogeneous participating media from voxel grids. Grids are read from Mitsuba's VOL format:
+// the bytes "VOL" and the version 3, then little-endian int32s for the encoding (1 for float32,
+// 3 for uint8 scaled to [0, 1]), the x, y and z resolutions and the channel count (which must
+// be 1), six float32s for the grid's bounding box (min x, y, z, then max x, y, z), and finally
+// the densities with x varying fastest, then y, then z.
+
+use std::{error::Error, fmt, fs, io, path::Path, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub(crate) enum VolError {
+    Io(io::Error),
+    // The file is not a VOL file this loader can read
+    Format(String),
+}
+
+impl fmt::Display for VolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VolError::Io(e) => write!(f, "{e}"),
+            VolError::Format(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl Error for VolError {}
+
+impl From<io::Error> for VolError {
+    fn from(e: io::Error) -> Self {
+        VolError::Io(e)
+    }
+}
+
+fn format_error(message: impl Into<String>) -> VolError {
+    VolError::Format(message.into())
+}
+
+// Densities sampled at the centers of nx * ny * nz cells filling bounds, read with trilinear
+// interpolation
+pub(crate) struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    values: Vec<f32>,
+    bounds: Aabb,
+}
+
+impl VoxelGrid {
+    // None unless values holds exactly one density per cell
+    pub(crate) fn new(
+        (nx, ny, nz): (usize, usize, usize),
+        values: Vec<f32>,
+        bounds: Aabb,
+    ) -> Option<Self> {
+        if nx == 0 || ny == 0 || nz == 0 || values.len() != nx * ny * nz {
+            return None;
+        }
+        Some(Self {
+            nx,
+            ny,
+            nz,
+            values,
+            bounds,
+        })
+    }
+
+    pub(crate) fn max_density(&self) -> f64 {
+        self.values.iter().fold(0.0f32, |max, &d| max.max(d)) as f64
+    }
+
+    pub(crate) fn density(&self, p: Point3) -> f64 {
+        let b = &self.bounds;
+        let local =
+            |x: f64, axis: &Interval, n: usize| (x - axis.min) / axis.size() * n as f64 - 0.5;
+        let (x, y, z) = (
+            local(p.x(), &b.x, self.nx),
+            local(p.y(), &b.y, self.ny),
+            local(p.z(), &b.z, self.nz),
+        );
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+        let (x0, y0, z0) = (x0 as i64, y0 as i64, z0 as i64);
+
+        let mut accum = 0.0;
+        for (dx, wx) in [(0, 1.0 - fx), (1, fx)] {
+            for (dy, wy) in [(0, 1.0 - fy), (1, fy)] {
+                for (dz, wz) in [(0, 1.0 - fz), (1, fz)] {
+                    accum += wx * wy * wz * self.value(x0 + dx, y0 + dy, z0 + dz);
+                }
+            }
+        }
+        accum
+    }
+
+    // The density of a cell, clamping to the grid's edges
+    fn value(&self, i: i64, j: i64, k: i64) -> f64 {
+        let clamp = |i: i64, n: usize| i.clamp(0, n as i64 - 1) as usize;
+        let (i, j, k) = (clamp(i, self.nx), clamp(j, self.ny), clamp(k, self.nz));
+        self.values[(k * self.ny + j) * self.nx + i] as f64
+    }
+}
+
+pub(crate) fn load_vol(path: &Path) -> Result<VoxelGrid, VolError> {
+    parse_vol(&fs::read(path)?)
+}
+
+pub(crate) fn parse_vol(bytes: &[u8]) -> Result<VoxelGrid, VolError> {
+    const HEADER: usize = 48;
+    if bytes.len() < HEADER || &bytes[..3] != b"VOL" {
+        return Err(format_error("not a VOL file"));
+    }
+    if bytes[3] != 3 {
+        return Err(format_error(format!(
+            "unsupported VOL version {}, expected 3",
+            bytes[3]
+        )));
+    }
+    let word = |i: usize| <[u8; 4]>::try_from(&bytes[4 + 4 * i..8 + 4 * i]).unwrap();
+    let int = |i: usize| i32::from_le_bytes(word(i));
+    let float = |i: usize| f32::from_le_bytes(word(i)) as f64;
+
+    let (encoding, channels) = (int(0), int(4));
+    if channels != 1 {
+        return Err(format_error(format!(
+            "expected a single channel density grid, found {channels} channels"
+        )));
+    }
+    let resolution = [int(1), int(2), int(3)];
+    if resolution.iter().any(|&n| n <= 0) {
+        return Err(format_error(format!(
+            "invalid grid resolution {}x{}x{}",
+            resolution[0], resolution[1], resolution[2]
+        )));
+    }
+    let [nx, ny, nz] = resolution.map(|n| n as usize);
+    let bounds = Aabb::from_points(
+        Point3::new(float(5), float(6), float(7)),
+        Point3::new(float(8), float(9), float(10)),
+    );
+
+    let count = nx
+        .checked_mul(ny)
+        .and_then(|n| n.checked_mul(nz))
+        .filter(|n| n.checked_mul(4).is_some())
+        .ok_or_else(|| format_error("grid resolution too large"))?;
+    let data = &bytes[HEADER..];
+    let values: Vec<f32> = match encoding {
+        1 if data.len() >= 4 * count => data
+            .chunks_exact(4)
+            .take(count)
+            .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+            .collect(),
+        3 if data.len() >= count => data[..count].iter().map(|&b| b as f32 / 255.0).collect(),
+        1 | 3 => return Err(format_error("the file ends before the grid does")),
+        _ => {
+            return Err(format_error(format!(
+                "unsupported VOL encoding {encoding}, expected 1 (float32) or 3 (uint8)"
+            )))
+        }
+    };
+    if values.iter().any(|d| !d.is_finite() || *d < 0.0) {
+        return Err(format_error("densities must be finite and non-negative"));
+    }
+    Ok(VoxelGrid::new((nx, ny, nz), values, bounds).unwrap())
+}
+
+// A medium whose density varies through a voxel grid, scaled by density_scale. Scattering points
+// are found by delta tracking: tentative collisions are drawn as though the whole grid were as
+// dense as its densest voxel, and each is kept with probability density / that maximum, the rest
+// being null collisions the ray passes straight through.
+pub(crate) struct GridMedium {
+    grid: VoxelGrid,
+    density_scale: f64,
+    majorant: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl GridMedium {
+    pub(crate) fn new(
+        grid: VoxelGrid,
+        density_scale: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        let majorant = density_scale * grid.max_density();
+        Self {
+            grid,
+            density_scale,
+            majorant,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for GridMedium {
+    fn hit(&self, r: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> Option<HitRecord> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let inside = self.grid.bounds.clip(r, ray_t)?;
+
+        let step = 1.0 / (self.majorant * r.direction().length());
+        let mut t = inside.min;
+        loop {
+            t -= (1.0 - sampler.random_f64()).ln() * step;
+            if t >= inside.max {
+                return None;
+            }
+            let density = self.density_scale * self.grid.density(r.at(t));
+            if sampler.random_f64() * self.majorant < density {
+                break;
+            }
+        }
+
+        let mut rec = HitRecord::new();
+        rec.t = t;
+        rec.p = r.at(t);
+        // A scattering point has no surface, so the normal is arbitrary
+        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.in_medium = true;
+        rec.mat = Some(self.phase_function.clone());
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.grid.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        interval::Interval,
+        material::Isotropic,
+        ray::Ray,
+        sampler::Sampler,
+        vec3::{Point3, Vec3},
+        volume::{parse_vol, GridMedium, VolError},
+    };
+
+    // A VOL file for a grid from (0, 0, 0) to (2, 1, 1)
+    fn vol(encoding: i32, resolution: [i32; 3], data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"VOL\x03".to_vec();
+        for n in [encoding, resolution[0], resolution[1], resolution[2], 1] {
+            bytes.extend(n.to_le_bytes());
+        }
+        for x in [0.0f32, 0.0, 0.0, 2.0, 1.0, 1.0] {
+            bytes.extend(x.to_le_bytes());
+        }
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn interpolates_between_voxel_centers() {
+        let data: Vec<u8> = [0.0f32, 1.0].iter().flat_map(|d| d.to_le_bytes()).collect();
+        let grid = parse_vol(&vol(1, [2, 1, 1], &data)).unwrap();
+        let at = |x| grid.density(Point3::new(x, 0.5, 0.5));
+        assert_eq!(at(0.5), 0.0);
+        assert_eq!(at(1.0), 0.5);
+        assert_eq!(at(1.5), 1.0);
+        assert_eq!(at(2.0), 1.0);
+        assert_eq!(grid.max_density(), 1.0);
+
+        let bytes = parse_vol(&vol(3, [2, 1, 1], &[0, 255])).unwrap();
+        assert_eq!(bytes.density(Point3::new(1.5, 0.5, 0.5)), 1.0);
+    }
+
+    #[test]
+    fn tracks_through_empty_and_dense_cells() {
+        let mut sampler = Sampler::new(0);
+        // The left half is empty and the right half dense
+        let grid = parse_vol(&vol(3, [2, 1, 1], &[0, 255])).unwrap();
+        let phase = Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0)));
+        let medium = GridMedium::new(grid, 2.0, phase);
+
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        let (mut scattered, rays) = (0, 10_000);
+        let origin = Point3::new(0.25, 0.5, 5.0);
+        for _ in 0..rays {
+            let empty = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0));
+            assert!(medium.hit(&empty, &ray_t, &mut sampler).is_none());
+
+            let dense = Ray::new(origin + Vec3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+            if let Some(rec) = medium.hit(&dense, &ray_t, &mut sampler) {
+                assert!(rec.t >= 4.0 && rec.t <= 5.0);
+                scattered += 1;
+            }
+        }
+        // Crossing one unit at density 2 scatters 1 - e^-2 of the rays
+        let expected = 1.0 - (-2.0f64).exp();
+        assert!((scattered as f64 / rays as f64 - expected).abs() < 0.02);
+    }
+
+    #[test]
+    fn reports_bad_files() {
+        let message = |bytes: &[u8]| match parse_vol(bytes) {
+            Err(VolError::Format(message)) => message,
+            _ => panic!("expected a format error"),
+        };
+        assert_eq!(message(b"PLY"), "not a VOL file");
+        assert_eq!(
+            message(&vol(2, [1, 1, 1], &[0, 0])),
+            "unsupported VOL encoding 2, expected 1 (float32) or 3 (uint8)"
+        );
+        assert_eq!(
+            message(&vol(3, [2, 2, 2], &[0; 7])),
+            "the file ends before the grid does"
+        );
+        assert_eq!(
+            message(&vol(1, [i32::MAX; 3], &[])),
+            "grid resolution too large"
+        );
+    }
+}